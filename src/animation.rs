@@ -0,0 +1,69 @@
+use crate::config::*;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// all animations are timed from the same instant so they run in phase
+fn epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+fn phase(period: Duration) -> f64 {
+    let p = period.as_secs_f64();
+    (epoch().elapsed().as_secs_f64() % p) / p
+}
+
+// on for the first half of every period, off for the second
+pub fn blink(period: Duration) -> bool {
+    phase(period) < 0.5
+}
+
+// time until blink() changes next, used as the frame interval for blinking
+pub fn until_blink(period: Duration) -> Duration {
+    let half = period.as_secs_f64() / 2.0;
+    let t = epoch().elapsed().as_secs_f64() % half;
+    Duration::from_secs_f64(half - t)
+}
+
+// blinking of anything urgent, all in phase
+pub fn urgent_blink() -> bool {
+    blink(Duration::from_millis(URGENT_BLINK_PERIOD))
+}
+
+pub fn until_urgent_blink() -> Duration {
+    until_blink(Duration::from_millis(URGENT_BLINK_PERIOD))
+}
+
+// goes smoothly from 0 to 1 and back once per period
+pub fn pulse(period: Duration) -> f64 {
+    0.5 - 0.5 * (2.0 * std::f64::consts::PI * phase(period)).cos()
+}
+
+// color between `from` (t = 0) and `to` (t = 1). An alpha of 0 is opaque
+// like for cairo_source_rgb_hex, so it's faded as 0xff.
+pub fn fade(from: u32, to: u32, t: f64) -> u32 {
+    let t = t.clamp(0.0, 1.0);
+    let opaque = |c: u32| if c >> 24 == 0 { c | 0xff00_0000 } else { c };
+    let (from, to) = (opaque(from), opaque(to));
+    let channel = |shift: u32| {
+        let a = ((from >> shift) & 0xff) as f64;
+        let b = ((to >> shift) & 0xff) as f64;
+        ((a + (b - a) * t).round() as u32) << shift
+    };
+    channel(24) | channel(16) | channel(8) | channel(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fade_colors() {
+        assert_eq!(fade(0x80000000, 0x80ff0000, 0.5), 0x80800000);
+        assert_eq!(fade(0x80000000, 0x80ff0000, 2.0), 0x80ff0000);
+        // without alpha both are opaque, not fading in from transparent
+        assert_eq!(fade(0x000000, 0xff0000, 0.5), 0xff800000);
+        assert_eq!(fade(0x000000, 0x80ff0000, 0.0), 0xff000000);
+        assert_eq!(fade(0x000000, 0x80ff0000, 1.0), 0x80ff0000);
+    }
+}
@@ -1,4 +1,7 @@
 use crate::modules::*;
+use crate::monitors::MonitorSource;
+use crate::scale::Scale;
+use crate::decoration::BlockShape;
 
 // pub const FONT: &str                = "Inconsolata Bold 18";
 pub const DATE_FORMAT: &str         = "%a %d.%m.%Y [%H:%M:%S]";
@@ -8,24 +11,144 @@ pub const TAG_MARGIN: f64           = 10.0;
 pub const TAG_SPACE: f64            = 2.0;
 pub const BLOCK_SPACE: f64          = 0.0;
 pub const STALONETRAY_ENABLED: bool = true;
+// transparency and rounded corners of the whole bar need a compositor,
+// without one the bar is drawn opaque and square
+pub const ARGB_VISUAL: bool         = false;
+pub const BAR_RADIUS: f64           = 0.0;
+pub const BAR_GAP: f64              = 0.0; // to the screen edges
+pub const BLOCK_RADIUS: f64         = 0.0;
 
-// hlwm monitor focus square size in % of height
+// block decorations. Powerline separators (Arrow, Slant) join blocks that
+// touch, set BLOCK_SPACE and TAG_SPACE to 0 for that. Lines and borders are
+// off at thickness 0.
+pub const BLOCK_SHAPE: BlockShape   = BlockShape::Flat;
+pub const BLOCK_BORDER: f64         = 0.0;
+pub const BLOCK_UNDERLINE: f64      = 0.0;
+pub const BLOCK_OVERLINE: f64       = 0.0;
+// sizes here and font sizes are for 96 DPI and scaled by this
+pub const SCALE: Scale = Scale::Fixed(1.0);
+// where the bars go with --multi
+pub const MULTI_BAR_MONITORS: MonitorSource = MonitorSource::Herbstluftwm;
+pub const WINDOW_TITLE_ELLIPSIS: &str = "…";
+
+// animations, all in milliseconds
+pub const ANIMATION_FRAME: u64       = 50;
+pub const URGENT_BLINK_PERIOD: u64   = 1000;
+pub const BATTERY_PULSE_PERIOD: u64  = 2000;
+
+// battery percentage at which the battery module starts pulsing
+pub const BATTERY_LOW: i32          = 10;
+
+// cpu graph
+pub const CPU_GRAPH_BAR_WIDTH: f64  = 3.0;
+pub const CPU_GRAPH_MARGIN: f64     = 3.0;
+
+// memory and backlight bars, height in % of bar height
+pub const MEMORY_BAR_WIDTH: f64     = 40.0;
+pub const MEMORY_BAR_HEIGHT: f64    = 0.4;
+pub const BACKLIGHT_BAR_WIDTH: f64  = 40.0;
+pub const BACKLIGHT_BAR_HEIGHT: f64 = 0.4;
+
+// wifi signal strength, from weakest to strongest
+pub const WIFI_SIGNAL_GLYPHS: [&str; 4] = ["▂___", "▂▄__", "▂▄▆_", "▂▄▆█"];
+pub const WIFI_DISCONNECTED: &str       = "wifi disconnected";
+
+// width of the monitor focus block left of the tags, in % of height
 pub const MONITOR_FOCUS_SIZE: f64   = 0.5;
 
-pub const C_RED: u32    = 0xf2777a;
-pub const C_GRAY1: u32  = 0x393939;
-pub const C_GRAY3: u32  = 0x747369;
-pub const C_GRAY4: u32  = 0xa09f93;
-pub const C_PURPLE: u32 = 0xcc99cc;
-pub const C_BLUE: u32   = 0x6699cc;
-pub const C_GREEN: u32  = 0x99cc99;
-pub const C_WHITE6: u32 = 0xe8e6df;
+// hlwm frame module
+pub const HLWM_LAYOUT_VERTICAL: &str   = "[V]";
+pub const HLWM_LAYOUT_HORIZONTAL: &str = "[H]";
+pub const HLWM_LAYOUT_MAX: &str        = "[M]";
+pub const HLWM_LAYOUT_GRID: &str       = "[G]";
+pub const HLWM_FLOATING_SYMBOL: &str   = "~";
+pub const HLWM_FULLSCREEN_SYMBOL: &str = "[F]";
+
+// media player status
+pub const MEDIA_PLAYING_SYMBOL: &str = "\u{25b6}";
+pub const MEDIA_PAUSED_SYMBOL: &str  = "\u{23f8}";
+pub const MEDIA_STOPPED_SYMBOL: &str = "\u{25a0}";
+
+// notifications, timeout in milliseconds, scroll speed in pixels per second.
+// The idle symbol is drawn without notifications, leave it empty to hide
+// the module then (do not disturb is toggled by middle clicking it).
+pub const NOTIFICATION_TIMEOUT: u64       = 5000;
+pub const NOTIFICATION_SCROLL_SPEED: f64  = 40.0;
+pub const NOTIFICATION_IDLE_SYMBOL: &str  = "\u{2709}";
+pub const NOTIFICATION_DND_SYMBOL: &str   = "DND";
+
+// keyboard lock indicators
+pub const KEYBOARD_CAPS_LOCK_SYMBOL: &str = "\u{21ea}";
+pub const KEYBOARD_NUM_LOCK_SYMBOL: &str  = "\u{21ed}";
+
+// calendar popup of the clock, margin around the day numbers
+pub const CALENDAR_CELL_MARGIN: f64 = 6.0;
+
+// agenda, seconds before an event's start at which it turns urgent
+pub const AGENDA_URGENT: u64 = 300;
+
+// timer phase names
+pub const TIMER_WORK_LABEL: &str  = "work";
+pub const TIMER_BREAK_LABEL: &str = "break";
+
+// 0xAARRGGBB, e.g. 0xcc393939 for a translucent gray. An alpha of 00 is
+// opaque like ff, 0x393939 still works.
+pub const C_RED: u32    = 0xfff2777a;
+pub const C_GRAY1: u32  = 0xff393939;
+pub const C_GRAY3: u32  = 0xff747369;
+pub const C_GRAY4: u32  = 0xffa09f93;
+pub const C_PURPLE: u32 = 0xffcc99cc;
+pub const C_BLUE: u32   = 0xff6699cc;
+pub const C_GREEN: u32  = 0xff99cc99;
+pub const C_WHITE6: u32 = 0xffe8e6df;
 
 pub const COLOR_BG: u32                          = C_GRAY1;
 pub const COLOR_TEXT: u32                        = C_WHITE6;
 pub const COLOR_BG_CLOCK: u32                    = C_GRAY3;
 pub const COLOR_BG_BATTERY: u32                  = C_GRAY4;
 pub const COLOR_BG_STALONETRAY: u32              = C_GRAY3;
+pub const COLOR_BG_WINDOW_TITLE: u32             = COLOR_BG;
+pub const COLOR_BG_HLWM_FRAME: u32               = C_GRAY3;
+pub const COLOR_BG_CPU: u32                      = C_GRAY4;
+pub const COLOR_BG_MEMORY: u32                   = C_GRAY3;
+pub const COLOR_BG_MEMORY_WARN: u32              = C_RED;
+pub const COLOR_BG_NETWORK: u32                  = C_GRAY4;
+pub const COLOR_BG_NETWORK_DOWN: u32             = C_RED;
+pub const COLOR_BG_WIFI: u32                     = C_GRAY3;
+pub const COLOR_BG_WIFI_DOWN: u32                = C_RED;
+pub const COLOR_BG_DISK: u32                     = C_GRAY4;
+pub const COLOR_BG_DISK_WARN: u32                = C_PURPLE;
+pub const COLOR_BG_DISK_CRITICAL: u32            = C_RED;
+pub const COLOR_BG_TEMPERATURE: u32              = C_GRAY3;
+pub const COLOR_BG_BACKLIGHT: u32                = C_GRAY4;
+pub const COLOR_BACKLIGHT_BAR: u32               = C_WHITE6;
+pub const COLOR_BG_MEDIA: u32                    = C_GRAY3;
+pub const COLOR_BG_NOTIFICATION_LOW: u32         = C_GRAY3;
+pub const COLOR_BG_NOTIFICATION: u32             = C_BLUE;
+pub const COLOR_BG_NOTIFICATION_CRITICAL: u32    = C_RED;
+pub const COLOR_BG_NOTIFICATION_DND: u32         = C_PURPLE;
+pub const COLOR_BG_KEYBOARD: u32                 = C_GRAY4;
+pub const COLOR_BG_KEYBOARD_CAPS_LOCK: u32       = C_PURPLE;
+pub const COLOR_BG_LOAD: u32                     = C_GRAY3;
+pub const COLOR_BG_LOAD_WARN: u32                = C_PURPLE;
+pub const COLOR_BG_LOAD_CRITICAL: u32            = C_RED;
+pub const COLOR_BG_MAIL: u32                     = C_GRAY3;
+pub const COLOR_BG_MAIL_UNREAD: u32              = C_BLUE;
+pub const COLOR_BG_TIMER_WORK: u32               = C_BLUE;
+pub const COLOR_BG_TIMER_BREAK: u32              = C_GREEN;
+pub const COLOR_BG_AGENDA: u32                   = C_GRAY4;
+pub const COLOR_BG_SYSTEMD: u32                  = C_GRAY3;
+pub const COLOR_BG_SYSTEMD_FAILED: u32           = C_RED;
+pub const COLOR_BG_SYSTEMD_ACTIVE: u32           = C_GRAY4;
+pub const COLOR_BG_SYSTEMD_INACTIVE: u32         = C_GRAY3;
+pub const COLOR_BG_SYSTEMD_CHANGING: u32         = C_BLUE;
+pub const COLOR_BG_CALENDAR: u32                 = C_GRAY1;
+pub const COLOR_BG_CALENDAR_HEADER: u32          = C_GRAY3;
+pub const COLOR_BG_CALENDAR_TODAY: u32           = C_BLUE;
+pub const COLOR_TEXT_CALENDAR_WEEKDAY: u32       = C_GRAY4;
+pub const COLOR_BLOCK_BORDER: u32                = C_GRAY1;
+pub const COLOR_BLOCK_UNDERLINE: u32             = C_BLUE;
+pub const COLOR_BLOCK_OVERLINE: u32              = C_BLUE;
 
 // hlwm tags
 pub const COLOR_EMPTY: u32                       = C_GRAY3;
@@ -35,6 +158,16 @@ pub const COLOR_THIS_MONITOR_FOCUSED: u32        = C_BLUE;
 pub const COLOR_DIFFERENT_MONITOR_UNFOCUSED: u32 = C_GREEN;
 pub const COLOR_DIFFERENT_MONITOR_FOCUSED: u32   = C_GREEN;
 pub const COLOR_URGENT_WINDOW: u32               = C_RED;
+pub const COLOR_URGENT_WINDOW_BLINK: u32         = COLOR_NON_EMPTY;
+
+// hlwm tag text
+pub const COLOR_TEXT_EMPTY: u32                       = COLOR_TEXT;
+pub const COLOR_TEXT_NON_EMPTY: u32                   = COLOR_TEXT;
+pub const COLOR_TEXT_THIS_MONITOR_UNFOCUSED: u32      = COLOR_TEXT;
+pub const COLOR_TEXT_THIS_MONITOR_FOCUSED: u32        = COLOR_TEXT;
+pub const COLOR_TEXT_DIFFERENT_MONITOR_UNFOCUSED: u32 = COLOR_TEXT;
+pub const COLOR_TEXT_DIFFERENT_MONITOR_FOCUSED: u32   = COLOR_TEXT;
+pub const COLOR_TEXT_URGENT_WINDOW: u32               = COLOR_TEXT;
 
 // monitor focus
 pub const COLOR_MONITOR_FOCUSED: u32             = C_BLUE;
@@ -47,17 +180,56 @@ pub fn modules_global() -> Vec<Box<dyn BarModule>> {
 }
 
 pub fn modules_left() -> Vec<Box<dyn BarModule>> {
-    vec![ Box::new(herbstluftwm::HerbstluftWM{}), ]
+    vec![
+        Box::new(workspaces::Workspaces::new(
+            Box::new(workspaces::herbstluftwm::HerbstluftWM::new()),
+            // Box::new(workspaces::bspwm::Bspwm::new()),
+            // Box::new(workspaces::i3::I3::new()),
+            // Box::new(workspaces::ewmh::Ewmh::new()),
+            // tag labels
+            vec![
+                // ("1", "\u{f120}"),
+            ],
+            false, // hide empty tags
+            false, // client counts
+        )),
+        // Box::new(hlwm_frame::HlwmFrame::new()),
+        // Box::new(window_title::WindowTitle::new(
+        //     80, window_title::Ellipsize::End, true)),
+    ]
 }
 
 pub fn modules_right() -> Vec<Box<dyn BarModule>> {
     vec![
-        Box::new(clock::Clock{}),
-        // Box::new(battery::Battery {
-        //     dirs: vec![
-        //         String::from("/sys/class/power_supply/BAT0/"),
-        //         String::from("/sys/class/power_supply/BAT1/"),
-        //     ],
-        // }),
+        Box::new(clock::Clock::new()),
+        // Box::new(cpu::Cpu::new(1000, cpu::CpuGraph::Sparkline(20))),
+        // Box::new(memory::Memory::new(
+        //     2000, "MEM {used}/{total}", 90.0, true, false)),
+        // Box::new(network::Network::new(
+        //     None, 1000, "{iface} {ipv4} \u{2193}{rx} \u{2191}{tx}")),
+        // Box::new(wifi::Wifi::new(None, 5000, "{glyph} {ssid} {bitrate}")),
+        // Box::new(disk::Disk::new(
+        //     disk::Mounts::Paths(vec!["/", "/home"]),
+        //     60000, "{mount} {free}", 80.0, 95.0)),
+        // Box::new(temperature::Temperature::new(
+        //     vec![("coretemp", "Package id 0"), ("thinkpad", "fan1")],
+        //     2000, "{value}")),
+        // Box::new(backlight::Backlight::new(None, 5, "\u{263c} {percent}%", true)),
+        // Box::new(media::Media::new("{status} {track}")),
+        // Box::new(notifications::Notifications::new("{app}: {summary} {body}", 300.0)),
+        // Box::new(keyboard::Keyboard::new(vec![("us", "EN")], "{layout}{caps}{num}")),
+        // Box::new(load::Load::new(5000, "{load1_raw} {load5_raw} up {uptime}", 0.7, 1.0)),
+        // Box::new(mail::Mail::new(vec!["~/mail/INBOX"], false, "\u{2709} {count}",
+        //                          Some("urxvt -e neomutt"))),
+        // Box::new(timer::Timer::new(25, 5, "{phase} {remaining}")),
+        // Box::new(agenda::Agenda::new(vec!["~/.calendars"], "{time} {summary} in {countdown}")),
+        // Box::new(systemd::Systemd::new(vec![
+        //     (systemd::Bus::User, "vdirsyncer.service"),
+        //     (systemd::Bus::User, "backup.service"),
+        // ], "\u{2717} {failed}")),
+        // Box::new(battery::Battery::new(vec![
+        //     String::from("/sys/class/power_supply/BAT0/"),
+        //     String::from("/sys/class/power_supply/BAT1/"),
+        // ])),
     ]
 }
@@ -0,0 +1,129 @@
+use crate::config::*;
+use crate::utils::*;
+use std::cell::Cell;
+
+// outline of the blocks on the bar, picked in config.rs
+#[allow(dead_code)]
+pub enum BlockShape {
+    // rectangles, with corners of BLOCK_RADIUS
+    Flat,
+    // rounded caps, half circles at both ends
+    Round,
+    // powerline separators. A block ends in an arrow or slant of its own
+    // color, and the block drawn after it starts with a notch the arrow
+    // fits in, so neighbors show each other's color without knowing it.
+    Arrow,
+    Slant,
+}
+
+// the notch reaches a bit into the arrow it takes in, their antialiased
+// edges would leave a seam of the bar's background otherwise
+const NOTCH_OVERLAP: f64 = 0.5;
+
+thread_local! {
+    // left and right end of the bar being drawn
+    static BAR_ENDS: Cell<(f64, f64)> = const { Cell::new((0.0, f64::INFINITY)) };
+}
+
+// set before rendering a bar, blocks starting at an end have nothing before
+// them to make room for
+pub fn set_bar_ends(left: f64, right: f64) {
+    BAR_ENDS.with(|e| e.set((left, right)));
+}
+
+// how far the end of a block reaches into the block after it
+pub fn overhang(height: f64) -> f64 {
+    match BLOCK_SHAPE {
+        BlockShape::Arrow | BlockShape::Slant => 0.5 * height,
+        BlockShape::Flat | BlockShape::Round => 0.0,
+    }
+}
+
+// whether a block starting at lead takes in the end of one before it
+pub fn notched(right: bool, lead: f64) -> bool {
+    let (left_end, right_end) = BAR_ENDS.with(|e| e.get());
+    let inside = if right { lead < right_end - 0.5 } else { lead > left_end + 0.5 };
+    inside && overhang(1.0) > 0.0
+}
+
+pub struct Block {
+    // drawn right to left, lead is the block's right end then
+    pub right: bool,
+    pub lead: f64,
+    // without the overhang at the end
+    pub width: f64,
+    pub height: f64,
+    pub notched: bool,
+}
+
+impl Block {
+    // x of a point u into the block, in drawing direction
+    fn x(&self, u: f64) -> f64 {
+        if self.right { self.lead - u } else { self.lead + u }
+    }
+
+    fn polygon(&self, cairo: &cairo::Context, points: &[(f64, f64)]) {
+        for (i, (u, y)) in points.iter().enumerate() {
+            if i == 0 {
+                cairo.move_to(self.x(*u), *y);
+            } else {
+                cairo.line_to(self.x(*u), *y);
+            }
+        }
+        cairo.close_path();
+    }
+
+    pub fn path(&self, cairo: &cairo::Context) {
+        let (w, h) = (self.width, self.height);
+        let s = overhang(h);
+        let o = NOTCH_OVERLAP;
+        let left = self.x(0.0).min(self.x(w));
+        match BLOCK_SHAPE {
+            BlockShape::Flat => cairo_rounded_rectangle(cairo, left, 0.0, w, h, BLOCK_RADIUS),
+            BlockShape::Round => cairo_rounded_rectangle(cairo, left, 0.0, w, h, 0.5 * h),
+            BlockShape::Arrow if self.notched => self.polygon(cairo, &[
+                (-o, 0.0), (w, 0.0), (w + s, 0.5 * h), (w, h), (-o, h), (s - o, 0.5 * h),
+            ]),
+            BlockShape::Arrow => self.polygon(cairo, &[
+                (0.0, 0.0), (w, 0.0), (w + s, 0.5 * h), (w, h), (0.0, h),
+            ]),
+            BlockShape::Slant if self.notched => self.polygon(cairo, &[
+                (s - o, 0.0), (w + s, 0.0), (w, h), (-o, h),
+            ]),
+            BlockShape::Slant => self.polygon(cairo, &[
+                (0.0, 0.0), (w + s, 0.0), (w, h), (0.0, h),
+            ]),
+        }
+    }
+
+    // background, over- and underline and border, all within the outline
+    pub fn draw(&self, cairo: &cairo::Context, color: u32) {
+        cairo_source_rgb_hex(cairo, color);
+        self.path(cairo);
+        cairo.fill_preserve();
+
+        cairo.save();
+        cairo.clip();
+        let s = overhang(self.height);
+        let left = self.x(-s).min(self.x(self.width + s));
+        let width = self.width + 2.0 * s;
+        if BLOCK_OVERLINE > 0.0 {
+            cairo_source_rgb_hex(cairo, COLOR_BLOCK_OVERLINE);
+            cairo.rectangle(left, 0.0, width, BLOCK_OVERLINE);
+            cairo.fill();
+        }
+        if BLOCK_UNDERLINE > 0.0 {
+            cairo_source_rgb_hex(cairo, COLOR_BLOCK_UNDERLINE);
+            cairo.rectangle(left, self.height - BLOCK_UNDERLINE, width, BLOCK_UNDERLINE);
+            cairo.fill();
+        }
+        if BLOCK_BORDER > 0.0 {
+            // half of the line is clipped away, the border stays inside
+            cairo_source_rgb_hex(cairo, COLOR_BLOCK_BORDER);
+            cairo.set_line_width(2.0 * BLOCK_BORDER);
+            self.path(cairo);
+            cairo.stroke();
+        }
+        cairo.restore();
+    }
+}
@@ -1,16 +1,24 @@
+
 use std::thread;
 use std::sync::{Arc, Mutex, Condvar};
+use std::time::Duration;
 
 mod config;
 use config::*;
 
 mod modules;
-use modules::BarModule;
+use modules::{BarModule, Click};
 
 mod utils;
 use utils::*;
 
 mod stalonetray;
+mod animation;
+mod netlink;
+mod popup;
+mod monitors;
+mod scale;
+mod decoration;
 
 enum Alignment {
     Left,
@@ -28,38 +36,98 @@ pub struct CairoTextBox {
 }
 
 impl CairoTextBox {
+    // a block on the bar, decorated as configured
     fn draw(&self, cairo: &cairo::Context, font: String) -> f64 {
+        self.draw_box(cairo, font, true, 0.0, 0.0).0
+    }
+
+    // a block with room for a graph or symbol, lead wide between the start of
+    // the block at align and the text, tail wide after the text. Returns the
+    // far end of the block and the left x of both rooms.
+    fn draw_with_room(&self, cairo: &cairo::Context, font: String,
+                      lead: f64, tail: f64) -> (f64, f64, f64) {
+        self.draw_box(cairo, font, true, lead, tail)
+    }
+
+    // a plain box, e.g. for popups
+    fn draw_flat(&self, cairo: &cairo::Context, font: String) -> f64 {
+        self.draw_box(cairo, font, false, 0.0, 0.0).0
+    }
+
+    fn draw_box(&self, cairo: &cairo::Context, font: String, decorated: bool,
+                lead: f64, tail: f64) -> (f64, f64, f64) {
         let pl = setup_pango_layout(cairo, font);
         pl.set_text(self.text.as_str());
         let (w, h) = pl.get_size();
         let w_text = (w / pango::SCALE) as f64;
         let h_text = (h / pango::SCALE) as f64;
-        let w_margins = w_text + 2.0 * self.margin;
 
-        let left;
-        match self.alignment {
-            Alignment::Left => left = self.align,
-            Alignment::Right => left = self.align - w_margins,
-        }
+        // room for the end of the block before this one
+        let right = matches!(self.alignment, Alignment::Right);
+        let notched = decorated && decoration::notched(right, self.align);
+        let w_notch = if notched { decoration::overhang(self.height) } else { 0.0 };
+        let w_inner = w_text + 2.0 * self.margin;
+        let w_margins = w_inner + w_notch + lead + tail;
+
+        let left = match self.alignment {
+            Alignment::Left => self.align,
+            Alignment::Right => self.align - w_margins,
+        };
 
         // background
-        cairo_source_rgb_hex(cairo, self.color_box);
-        cairo.rectangle(left, 0.0, w_margins, self.height);
-        cairo.fill();
+        if decorated {
+            let block = decoration::Block {
+                right,
+                lead: self.align,
+                width: w_margins,
+                height: self.height,
+                notched,
+            };
+            block.draw(cairo, self.color_box);
+        } else {
+            cairo_source_rgb_hex(cairo, self.color_box);
+            cairo_rounded_rectangle(cairo, left, 0.0, w_margins, self.height, BLOCK_RADIUS);
+            cairo.fill();
+        }
 
-        // text
+        // the notch is at align, then the lead room, the text and the tail
+        let (lead_left, text_left, tail_left) = match self.alignment {
+            Alignment::Left => {
+                let lead_left = left + w_notch;
+                (lead_left, lead_left + lead, lead_left + lead + w_inner)
+            }
+            Alignment::Right => (self.align - w_notch - lead, left + tail, left),
+        };
         cairo_source_rgb_hex(cairo, self.color_text);
-        cairo.move_to(left + self.margin, 0.5 * (self.height - h_text));
-        pangocairo::show_layout(&cairo, &pl);
+        cairo.move_to(text_left + self.margin, 0.5 * (self.height - h_text));
+        pangocairo::show_layout(cairo, &pl);
 
-        match self.alignment {
+        let end = match self.alignment {
             Alignment::Left => left + w_margins,
             Alignment::Right => left,
-        }
+        };
+        (end, lead_left, tail_left)
     }
 }
 
-fn draw_thread(x_state: XState, bar_state: Arc<(Mutex<BarState>,Condvar)>) {
+// where a left or right module was drawn during the last full redraw
+struct Extent {
+    right: bool,
+    index: usize,
+    align: f64,
+    x0: f64,
+    x1: f64,
+}
+
+// shortest frame interval any module currently wants
+fn animation_interval(b: &BarState) -> Option<Duration> {
+    b.modules_left.iter()
+        .chain(b.modules_right.iter())
+        .filter_map(|m| m.animation())
+        .min()
+}
+
+fn draw_thread(conn: Arc<xcb::Connection>, bar_state: Arc<(Mutex<BarState>,Condvar)>) {
     loop {
         let mut b = bar_state.0.lock().unwrap();
         let c = &bar_state.1;
@@ -68,39 +136,104 @@ fn draw_thread(x_state: XState, bar_state: Arc<(Mutex<BarState>,Condvar)>) {
             break;
         }
 
-        // let mut signaled = lock.lock().unwrap();
+        // animated modules wake us up without a redraw being signaled
+        let frame = animation_interval(&b);
         while !b.redraw_signaled {
-            b = c.wait(b).unwrap();
+            match frame {
+                None => b = c.wait(b).unwrap(),
+                Some(d) => {
+                    let (guard, timeout) = c.wait_timeout(b, d).unwrap();
+                    b = guard;
+                    if timeout.timed_out() {
+                        break;
+                    }
+                }
+            }
         }
 
-        // render modules
-        for m in b.modules_global.iter() {
-            m.render(b.dyn_config.clone(), &x_state.cairo, 0.0);
+        if !b.redraw_signaled {
+            for bar in b.bars.iter() {
+                draw_animation_frame(&conn, &b, bar);
+            }
+            continue;
         }
 
-        let mut l = 0.0;
-        for m in b.modules_left.iter() {
-            l = m.render(b.dyn_config.clone(), &x_state.cairo, l) + BLOCK_SPACE;
-        }
+        // render modules, the same ones on every bar
+        let b = &mut *b;
+        for bar in b.bars.iter_mut() {
+            let dyn_config = bar.dyn_config.logical();
+            decoration::set_bar_ends(0.0, dyn_config.width);
+            for m in b.modules_global.iter() {
+                m.render(dyn_config.clone(), &bar.cairo, 0.0);
+            }
 
-        let mut r = b.dyn_config.width;
-        for m in b.modules_right.iter() {
-            r = m.render(b.dyn_config.clone(), &x_state.cairo, r) - BLOCK_SPACE;
-        }
+            let extents = &mut bar.extents;
+            extents.clear();
+            let mut l = 0.0;
+            for (i, m) in b.modules_left.iter().enumerate() {
+                let x1 = m.render(dyn_config.clone(), &bar.cairo, l);
+                extents.push(Extent { right: false, index: i, align: l, x0: l, x1 });
+                l = x1 + BLOCK_SPACE;
+            }
 
-        xcb::xproto::copy_area(&x_state.connection,
-                               x_state.pixmap,
-                               x_state.window,
-                               x_state.gcontext,
-                               0, 0, 0, 0,
-                               b.dyn_config.width as u16,
-                               b.dyn_config.height as u16);
-        x_state.connection.flush();
+            let mut r = dyn_config.width;
+            for (i, m) in b.modules_right.iter().enumerate() {
+                let x0 = m.render(dyn_config.clone(), &bar.cairo, r);
+                extents.push(Extent { right: true, index: i, align: r, x0, x1: r });
+                r = x0 - BLOCK_SPACE;
+            }
+
+            copy_to_window(&conn, bar, 0.0, dyn_config.width);
+        }
 
         b.redraw_signaled = false;
     }
 }
 
+// redraw only the animated modules, each within its last extent. Blocks
+// reach into their neighbors with powerline separators, the area is widened
+// by that and the neighbors are drawn again within it.
+fn draw_animation_frame(conn: &xcb::Connection, b: &BarState, bar: &Bar) {
+    let cr = &bar.cairo;
+    let dyn_config = bar.dyn_config.logical();
+    decoration::set_bar_ends(0.0, dyn_config.width);
+    let overhang = decoration::overhang(dyn_config.height);
+    for e in bar.extents.iter() {
+        if b.module(e).animation().is_none() || e.x1 <= e.x0 {
+            continue;
+        }
+        let x0 = (e.x0 - overhang).max(0.0);
+        let x1 = (e.x1 + overhang).min(dyn_config.width);
+
+        cr.save();
+        cr.rectangle(x0, 0.0, x1 - x0, dyn_config.height);
+        cr.clip();
+        for g in b.modules_global.iter() {
+            g.render(dyn_config.clone(), cr, 0.0);
+        }
+        for f in bar.extents.iter().filter(|f| f.x0 < x1 && f.x1 > x0) {
+            b.module(f).render(dyn_config.clone(), cr, f.align);
+        }
+        cr.restore();
+
+        copy_to_window(conn, bar, x0, x1);
+    }
+}
+
+// x0 and x1 as modules see them, the area is widened to whole pixels
+fn copy_to_window(conn: &xcb::Connection, bar: &Bar, x0: f64, x1: f64) {
+    let scale = bar.dyn_config.scale;
+    let (x0, x1) = ((x0 * scale).floor(), (x1 * scale).ceil());
+    xcb::xproto::copy_area(conn,
+                           bar.pixmap,
+                           bar.window,
+                           bar.gcontext,
+                           x0 as i16, 0, x0 as i16, 0,
+                           (x1 - x0) as u16,
+                           bar.dyn_config.height as u16);
+    conn.flush();
+}
+
 // non-static configuration (given as arg)
 #[derive(Clone, Default)]
 pub struct DynamicConfig {
@@ -112,31 +245,232 @@ pub struct DynamicConfig {
     font: String,
     stalone_offset: i32,
     stalone_enabled: bool,
+    // size of a config.rs pixel in device pixels
+    scale: f64,
+    // the bar has an alpha channel and a compositor shows it
+    transparent: bool,
+}
+
+impl DynamicConfig {
+    // what modules get: the bar is drawn with a scaled cairo context, so
+    // its size is given in unscaled pixels
+    fn logical(&self) -> DynamicConfig {
+        DynamicConfig {
+            width: self.width / self.scale,
+            height: self.height / self.scale,
+            ..self.clone()
+        }
+    }
 }
 
-struct XState {
+// a bar window, there's one per monitor with --multi
+struct Bar {
+    dyn_config: DynamicConfig,
+    // the monitor the bar was created for, None if it was placed by the
+    // command line arguments
+    monitor: Option<monitors::Monitor>,
+    extents: Vec<Extent>,
     cairo: cairo::Context,
-    connection: Arc<xcb::Connection>,
     window: xcb::xproto::Window,
     pixmap: xcb::xproto::Pixmap,
     gcontext: xcb::xproto::Gcontext,
+    // only for transparent bars, others use the root window's
+    colormap: Option<xcb::xproto::Colormap>,
+}
+
+impl Bar {
+    fn new(conn: &xcb::Connection, screen: &xcb::Screen, mut dyn_config: DynamicConfig,
+           monitor: Option<monitors::Monitor>) -> Bar {
+        // keep BAR_GAP to the edges of the screen, or the monitor
+        let gap = BAR_GAP * dyn_config.scale;
+        let middle = monitor.map_or(0.5 * screen.height_in_pixels() as f64,
+                                    |m| m.y as f64 + 0.5 * m.height as f64);
+        dyn_config.x_offset += gap;
+        dyn_config.y_offset += if dyn_config.y_offset > middle { -gap } else { gap };
+        dyn_config.width -= 2.0 * gap;
+        dyn_config.stalone_offset += gap as i32;
+
+        // without a 32 bit visual the bar stays opaque
+        let argb = get_argb_visual_type(screen).filter(|_| dyn_config.transparent);
+        dyn_config.transparent = argb.is_some();
+        let colormap = argb.map(|v| {
+            let colormap = conn.generate_id();
+            xcb::create_colormap(conn, xcb::COLORMAP_ALLOC_NONE as u8, colormap, screen.root(),
+                                 v.visual_id());
+            colormap
+        });
+        let (depth, visual) = match argb {
+            Some(v) => (32, v),
+            None => (screen.root_depth(), get_root_visual_type(screen)),
+        };
+
+        let win = conn.generate_id();
+
+        let ev_mask = xcb::EVENT_MASK_EXPOSURE
+            | xcb::EVENT_MASK_KEY_PRESS
+            | xcb::EVENT_MASK_BUTTON_PRESS;
+        let mut value_list = vec![
+            (xcb::CW_EVENT_MASK, ev_mask),
+            (xcb::CW_OVERRIDE_REDIRECT, 1)
+        ];
+        // a window of another depth than its parent needs its own colormap
+        // and border
+        if let Some(c) = colormap {
+            value_list.push((xcb::CW_BACK_PIXEL, 0));
+            value_list.push((xcb::CW_BORDER_PIXEL, 0));
+            value_list.push((xcb::CW_COLORMAP, c));
+        }
+        xcb::create_window(conn,
+                           depth,
+                           win,
+                           screen.root(),
+                           dyn_config.x_offset as i16,
+                           dyn_config.y_offset as i16,
+                           dyn_config.width as u16,
+                           dyn_config.height as u16,
+                           0,
+                           xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
+                           visual.visual_id(),
+                           &value_list);
+        xcb::map_window(conn, win);
+        conn.flush();
+
+        // set up pixmap
+        // we can't draw on the window directly, we need the double buffering
+        // from xcb_copy
+        let pixmap: xcb::xproto::Pixmap = conn.generate_id();
+        xcb::xproto::create_pixmap(conn,
+                                   depth,
+                                   pixmap,
+                                   screen.root(),
+                                   dyn_config.width as u16,
+                                   dyn_config.height as u16);
+
+        // set up graphics context
+        let gcontext = conn.generate_id();
+        let gc_value_list = &[
+            (xcb::GC_FOREGROUND, screen.black_pixel()),
+            (xcb::GC_GRAPHICS_EXPOSURES, 0)
+        ];
+        xcb::create_gc(conn, gcontext, win, gc_value_list);
+
+        // set up cairo
+        let mut visual = visual;
+        let raw_visual_type = &mut visual.base as *mut _ as *mut cairo_sys::xcb_visualtype_t;
+        let raw_cairo_conn = conn.get_raw_conn() as *mut cairo_sys::xcb_connection_t;
+
+        let cairo_conn;
+        let visual_type;
+        unsafe {
+            cairo_conn = cairo::XCBConnection::from_raw_none(raw_cairo_conn);
+            visual_type = cairo::XCBVisualType::from_raw_none(raw_visual_type);
+        }
+
+        let surface = cairo::XCBSurface::create(&cairo_conn,
+                                                &cairo::XCBDrawable(pixmap),
+                                                &visual_type,
+                                                dyn_config.width as i32,
+                                                dyn_config.height as i32)
+            .expect("failed to create XCBSurface");
+
+        // modules draw in config.rs pixels. Text is scaled along, pango lays
+        // it out at 96 DPI through the context's matrix.
+        let cairo = cairo::Context::new(&surface);
+        cairo.scale(dyn_config.scale, dyn_config.scale);
+
+        Bar {
+            dyn_config,
+            monitor,
+            extents: Vec::new(),
+            cairo,
+            window: win,
+            pixmap,
+            gcontext,
+            colormap,
+        }
+    }
+
+    fn destroy(self, conn: &xcb::Connection) {
+        xcb::destroy_window(conn, self.window);
+        xcb::free_pixmap(conn, self.pixmap);
+        xcb::free_gc(conn, self.gcontext);
+        if let Some(c) = self.colormap {
+            xcb::free_colormap(conn, c);
+        }
+        conn.flush();
+    }
 }
-unsafe impl Send for XState {}
 
 pub struct BarState {
     redraw_signaled: bool,
     bar_closed: bool,
-    dyn_config: DynamicConfig,
+    bars: Vec<Bar>,
     modules_left: Vec<Box<dyn BarModule>>,
     modules_right: Vec<Box<dyn BarModule>>,
     modules_global: Vec<Box<dyn BarModule>>,
 }
 unsafe impl Send for BarState {}
 
+impl BarState {
+    fn module(&self, e: &Extent) -> &dyn BarModule {
+        if e.right {
+            self.modules_right[e.index].as_ref()
+        } else {
+            self.modules_left[e.index].as_ref()
+        }
+    }
+}
+
+// one bar per monitor, created, moved and destroyed as monitors come and go.
+// template has the height, font and stalonetray settings, the height is for
+// 96 DPI and scaled like everything else on the bar.
+fn sync_bars(conn: &xcb::Connection, screen_num: i32, template: &DynamicConfig,
+             bar_state: &Arc<(Mutex<BarState>, Condvar)>) {
+    let setup = conn.get_setup();
+    let screen = setup.roots().nth(screen_num as usize).unwrap();
+    let screen_width = screen.width_in_pixels() as i32;
+    let monitors = monitors::detect(conn, &screen);
+
+    let mut b = bar_state.0.lock().unwrap();
+    let mut old = std::mem::take(&mut b.bars).into_iter();
+    for (i, m) in monitors.iter().enumerate() {
+        let scale = scale::detect(conn, &screen, m.x, m.y);
+        match old.next() {
+            // the pixmap has the monitor's size, so only unchanged bars are
+            // kept
+            Some(bar) if bar.monitor == Some(*m) && bar.dyn_config.scale == scale => {
+                b.bars.push(bar);
+                continue;
+            }
+            Some(bar) => bar.destroy(conn),
+            None => {}
+        }
+        let dyn_config = DynamicConfig {
+            x_offset: m.x as f64,
+            y_offset: m.y as f64,
+            width: m.width as f64,
+            height: (template.height * scale).round(),
+            monitor: i as i32,
+            // stalonetray can only run once, it goes on the first monitor
+            stalone_offset: screen_width - (m.x + m.width as i32),
+            stalone_enabled: template.stalone_enabled && i == 0,
+            scale,
+            ..template.clone()
+        };
+        b.bars.push(Bar::new(conn, &screen, dyn_config, Some(*m)));
+    }
+    for bar in old {
+        bar.destroy(conn);
+    }
+    b.redraw_signaled = true;
+    bar_state.1.notify_one();
+}
+
 fn main() {
     // parse arguments
     let args: Vec<String> = std::env::args().collect();
     let mut dyn_config: DynamicConfig = Default::default();
+    let multi = args.len() == 5 && args[1] == "--multi";
     match args.len() {
         9 => {
             dyn_config.x_offset = args[1].parse::<f64>().unwrap();
@@ -148,6 +482,11 @@ fn main() {
             dyn_config.stalone_offset = args[7].parse::<i32>().unwrap();
             dyn_config.stalone_enabled = args[8].parse::<bool>().unwrap();
         },
+        5 if multi => {
+            dyn_config.height = args[2].parse::<f64>().unwrap();
+            dyn_config.font = args[3].clone();
+            dyn_config.stalone_enabled = args[4].parse::<bool>().unwrap();
+        },
         _ => panic!("wrong number of arguments"),
     }
 
@@ -155,7 +494,7 @@ fn main() {
     let bar_state = Arc::new((Mutex::new(BarState {
         redraw_signaled: false,
         bar_closed:      false,
-        dyn_config:      dyn_config.clone(),
+        bars:            Vec::new(),
         modules_left:    modules_left(),
         modules_right:   modules_right(),
         modules_global:  modules_global(),
@@ -163,77 +502,21 @@ fn main() {
 
     // set up xcb
     let (conn, screen_num) = xcb::Connection::connect(None).unwrap();
-    let setup = conn.get_setup();
-    let screen = setup.roots().nth(screen_num as usize).unwrap();
-    let win = conn.generate_id();
-
-    let ev_mask = xcb::EVENT_MASK_EXPOSURE | xcb::EVENT_MASK_KEY_PRESS;
-    let value_list = &[
-        (xcb::CW_EVENT_MASK, ev_mask),
-        (xcb::CW_OVERRIDE_REDIRECT, 1)
-    ];
-    xcb::create_window(&conn,
-                       xcb::COPY_FROM_PARENT as u8,
-                       win,
-                       screen.root(),
-                       dyn_config.x_offset as i16,
-                       dyn_config.y_offset as i16,
-                       dyn_config.width as u16,
-                       dyn_config.height as u16,
-                       0,
-                       xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
-                       screen.root_visual(),
-                       value_list);
-    xcb::map_window(&conn, win);
-    conn.flush();
-
-    // set up pixmap
-    // we can't draw on the window directly, we need the double buffering
-    // from xcb_copy
-    let pixmap: xcb::xproto::Pixmap = conn.generate_id();
-    xcb::xproto::create_pixmap(&conn,
-                               screen.root_depth(),
-                               pixmap,
-                               screen.root(),
-                               dyn_config.width as u16,
-                               dyn_config.height as u16);
-
-    // set up graphics context
-    let gcontext = conn.generate_id();
-    let gc_value_list = &[
-        (xcb::GC_FOREGROUND, screen.black_pixel()),
-        (xcb::GC_GRAPHICS_EXPOSURES, 0)
-    ];
-    xcb::create_gc(&conn, gcontext, win, gc_value_list);
-
-    // set up cairo
-    let root_visual_type = &mut get_root_visual_type(&screen).base
-        as *mut _ as *mut cairo_sys::xcb_visualtype_t;
-    let raw_cairo_conn = conn.get_raw_conn() as *mut cairo_sys::xcb_connection_t;
-
-    let cairo_conn;
-    let visual_type;
-    unsafe {
-        cairo_conn = cairo::XCBConnection::from_raw_none(raw_cairo_conn);
-        visual_type = cairo::XCBVisualType::from_raw_none(root_visual_type);
-    }
-
-    let surface = cairo::XCBSurface::create(&cairo_conn,
-                                            &cairo::XCBDrawable(pixmap),
-                                            &visual_type,
-                                            dyn_config.width as i32,
-                                            dyn_config.height as i32)
-        .expect("failed to create XCBSurface");
-    let cr = cairo::Context::new(&surface);
-
     let conn_arc = Arc::new(conn);
-    let x_state = XState {
-        cairo:      cr,
-        connection: conn_arc.clone(),
-        window:     win,
-        pixmap:     pixmap,
-        gcontext:   gcontext,
-    };
+    dyn_config.transparent = ARGB_VISUAL && compositor_running(&conn_arc, screen_num);
+    let mut randr_event = None;
+    if multi {
+        let root = conn_arc.get_setup().roots().nth(screen_num as usize).unwrap().root();
+        randr_event = monitors::watch(&conn_arc, root);
+        sync_bars(&conn_arc, screen_num, &dyn_config, &bar_state);
+    } else {
+        let setup = conn_arc.get_setup();
+        let screen = setup.roots().nth(screen_num as usize).unwrap();
+        dyn_config.scale = scale::detect(&conn_arc, &screen, dyn_config.x_offset as i32,
+                                         dyn_config.y_offset as i32);
+        let bar = Bar::new(&conn_arc, &screen, dyn_config.clone(), None);
+        bar_state.0.lock().unwrap().bars.push(bar);
+    }
 
     // start event generators
     {
@@ -243,17 +526,19 @@ fn main() {
             b.modules_left.iter().chain(
                 b.modules_right.iter()));
         for m in all_modules {
+            m.x_init(&conn_arc, screen_num);
             m.event_generator(bar_state.clone());
         }
     }
 
     // start drawing thread
     let b0 = bar_state.clone();
+    let c0 = conn_arc.clone();
     let draw_thread_handler = thread::spawn(move || {
-        draw_thread(x_state, b0);
+        draw_thread(c0, b0);
     });
 
-    // run stalonetray if enabled
+    // run stalonetray if enabled, on the first bar
     if STALONETRAY_ENABLED && dyn_config.stalone_enabled {
         stalonetray::run(bar_state.clone());
     }
@@ -265,13 +550,70 @@ fn main() {
             None => break,
             Some(event) => {
                 let r = event.response_type() & !0x80;
-                match r {
+                // popups of modules share our connection, their events are
+                // passed on below
+                let window = match r {
                     xcb::EXPOSE => {
+                        let e: &xcb::ExposeEvent = unsafe { xcb::cast_event(&event) };
+                        Some(e.window())
+                    }
+                    xcb::BUTTON_PRESS => {
+                        let e: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(&event) };
+                        Some(e.event())
+                    }
+                    _ => None,
+                };
+                let on_bar = window.is_some_and(|w| {
+                    bar_state.0.lock().unwrap().bars.iter().any(|bar| bar.window == w)
+                });
+                match r {
+                    xcb::EXPOSE if on_bar => {
                         signal_bar_redraw(bar_state.clone());
                     }
-                    xcb::KEY_PRESS => {
+                    xcb::BUTTON_PRESS if on_bar => {
+                        let press: &xcb::ButtonPressEvent = unsafe {
+                            xcb::cast_event(&event)
+                        };
+                        let redraw = {
+                            let b = bar_state.0.lock().unwrap();
+                            let bar = b.bars.iter().find(|bar| bar.window == press.event());
+                            bar.is_some_and(|bar| {
+                                let x = press.event_x() as f64 / bar.dyn_config.scale;
+                                bar.extents.iter()
+                                    .find(|e| e.x0 <= x && x < e.x1)
+                                    .is_some_and(|e| b.module(e).click(&conn_arc, &Click {
+                                        button: press.detail(),
+                                        x0: e.x0,
+                                        x1: e.x1,
+                                        dyn_config: bar.dyn_config.logical(),
+                                    }))
+                            })
+                        };
+                        if redraw {
+                            signal_bar_redraw(bar_state.clone());
+                        }
+                    }
+                    _ => {
+                        // outputs changed, a RRScreenChangeNotify or RRNotify
+                        if randr_event.is_some_and(|e| r == e || r == e + 1) {
+                            sync_bars(&conn_arc, screen_num, &dyn_config, &bar_state);
+                        }
+                        // let modules that listen on our connection see it,
+                        // RandR events included
+                        let mut redraw = false;
+                        {
+                            let b = bar_state.0.lock().unwrap();
+                            let all_modules = b.modules_global.iter().chain(
+                                b.modules_left.iter().chain(
+                                    b.modules_right.iter()));
+                            for m in all_modules {
+                                redraw |= m.x_event(&conn_arc, &event);
+                            }
+                        }
+                        if redraw {
+                            signal_bar_redraw(bar_state.clone());
+                        }
                     }
-                    _ => {}
                 }
             }
         }
@@ -1,13 +1,93 @@
 use crate::DynamicConfig;
 use crate::BarState;
 use std::sync::{Arc, Mutex, Condvar};
+use std::time::Duration;
+
+// mouse buttons as reported in ButtonPress events
+pub const BUTTON_LEFT: u8   = 1;
+pub const BUTTON_MIDDLE: u8 = 2;
+pub const BUTTON_RIGHT: u8  = 3;
+pub const SCROLL_UP: u8     = 4;
+pub const SCROLL_DOWN: u8   = 5;
+
+// a button press on a module, x0 and x1 are where the module was drawn
+// during the last full redraw
+pub struct Click {
+    pub button: u8,
+    pub x0: f64,
+    pub x1: f64,
+    // where the bar is, e.g. to place a popup::Popup next to the module
+    pub dyn_config: DynamicConfig,
+}
 
 pub trait BarModule {
     fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64;
     fn event_generator(&self, sync: Arc<(Mutex<BarState>, Condvar)>);
+
+    // called once with the bar's X connection, before event_generator
+    fn x_init(&self, _conn: &xcb::Connection, _screen_num: i32) {}
+
+    // X events the main loop doesn't handle itself, returns true if the bar
+    // needs to be redrawn
+    fn x_event(&self, _conn: &xcb::Connection, _event: &xcb::GenericEvent) -> bool {
+        false
+    }
+
+    // a mouse button was pressed on the module, returns true if the bar
+    // needs to be redrawn. Called from the X event loop with the bar state
+    // locked, so it shouldn't block for long.
+    fn click(&self, _conn: &xcb::Connection, _click: &Click) -> bool {
+        false
+    }
+
+    // time until the module wants to draw its next animation frame, None
+    // while it has nothing to animate. Asked for every bar, so the answer
+    // comes from the module's state, not from the last render. Animation
+    // frames redraw the area the module was last drawn in, widened by the
+    // block overhang, and every module in it.
+    fn animation(&self) -> Option<Duration> {
+        None
+    }
 }
 
+// modules that config.rs may leave out are allowed to be unused
 pub mod basebar;
 pub mod herbstluftwm;
+#[allow(dead_code)]
+pub mod hlwm_frame;
 pub mod clock;
+#[allow(dead_code)]
 pub mod battery;
+#[allow(dead_code)]
+pub mod cpu;
+#[allow(dead_code)]
+pub mod memory;
+#[allow(dead_code)]
+pub mod network;
+#[allow(dead_code)]
+pub mod wifi;
+#[allow(dead_code)]
+pub mod disk;
+#[allow(dead_code)]
+pub mod temperature;
+#[allow(dead_code)]
+pub mod backlight;
+#[allow(dead_code)]
+pub mod media;
+#[allow(dead_code)]
+pub mod notifications;
+#[allow(dead_code)]
+pub mod keyboard;
+#[allow(dead_code)]
+pub mod load;
+#[allow(dead_code)]
+pub mod mail;
+#[allow(dead_code)]
+pub mod timer;
+#[allow(dead_code)]
+pub mod agenda;
+#[allow(dead_code)]
+pub mod systemd;
+#[allow(dead_code)]
+pub mod window_title;
+pub mod workspaces;
@@ -0,0 +1,999 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::animation;
+use crate::config::*;
+use crate::utils::*;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc, Weekday};
+use super::BarModule;
+
+pub struct Agenda {
+    // .ics files or directories holding them, e.g. a vdirsyncer storage.
+    // "~/" is relative to $HOME
+    paths: Vec<PathBuf>,
+    // placeholders: {summary} {time} {countdown}
+    format: &'static str,
+    // today's timed events, sorted by start
+    state: Arc<Mutex<Vec<Instance>>>,
+}
+
+// a VEVENT, times are local unless the event has a zone
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub uid: String,
+    pub summary: String,
+    pub start: NaiveDateTime,
+    // zone of DTSTART, start, exdates and recurrence_id are wall times in it
+    pub tz: Option<Zone>,
+    pub duration: chrono::Duration,
+    pub all_day: bool,
+    pub rule: Option<Rule>,
+    pub exdates: Vec<NaiveDateTime>,
+    // start of the recurring event's instance this event replaces
+    pub recurrence_id: Option<NaiveDateTime>,
+    pub cancelled: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+// the parts of an RRULE we understand, BYSETPOS and the like are ignored
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub freq: Freq,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<NaiveDateTime>,
+    // (n, weekday) for the n-th weekday of the month or year, 0 is every
+    // one and negative n count from the end
+    pub by_day: Vec<(i32, Weekday)>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+}
+
+// a VTIMEZONE, switching between its STANDARD and DAYLIGHT observances
+#[derive(Clone, Debug, PartialEq)]
+pub struct Zone {
+    pub id: String,
+    pub observances: Vec<Observance>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observance {
+    // first onset, in the wall time before it. Later ones follow the rule
+    // and rdates.
+    pub start: NaiveDateTime,
+    pub rule: Option<Rule>,
+    pub rdates: Vec<NaiveDateTime>,
+    // UTC offsets in seconds
+    pub offset_from: i64,
+    pub offset_to: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instance {
+    pub summary: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+// content lines, with folded lines joined
+fn unfold(s: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for l in s.lines() {
+        match (l.strip_prefix(' ').or_else(|| l.strip_prefix('\t')), lines.last_mut()) {
+            (Some(cont), Some(last)) => last.push_str(cont),
+            _ => lines.push(String::from(l)),
+        }
+    }
+    lines
+}
+
+type Params = Vec<(String, String)>;
+
+// "NAME;PARAM=value;...:VALUE" into (NAME, params, VALUE), parameter values
+// may be quoted and contain ':'
+fn parse_property(line: &str) -> Option<(String, Params, String)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find(|&(_, c)| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        c == ':' && !quoted
+    })?.0;
+    let mut head = line[..colon].split(';');
+    let name = head.next()?.to_ascii_uppercase();
+    let params = head
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.to_ascii_uppercase(), String::from(v.trim_matches('"'))))
+        .collect();
+    Some((name, params, String::from(&line[colon + 1..])))
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push(' '),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+// DATE or DATE-TIME value as local time and whether it was a DATE. UTC
+// times are converted, floating times are local. Times with a TZID are
+// returned as they are, parse_calendar converts them with the VTIMEZONE
+// of the file.
+pub fn parse_datetime(value: &str) -> Option<(NaiveDateTime, bool)> {
+    if value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some((date.and_hms(0, 0, 0), true));
+    }
+    match value.strip_suffix('Z') {
+        Some(utc) => {
+            let t = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+            Some((Utc.from_utc_datetime(&t).with_timezone(&Local).naive_local(), false))
+        }
+        None => Some((NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?, false)),
+    }
+}
+
+// "+0100", "-0500" or "+053000" in seconds
+pub fn parse_offset(value: &str) -> Option<i64> {
+    let (sign, digits) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    if !(digits.len() == 4 || digits.len() == 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n = |i: usize| digits.get(i..i + 2).map_or(0, |d| d.parse::<i64>().unwrap_or(0));
+    Some(sign * (n(0) * 3600 + n(2) * 60 + n(4)))
+}
+
+// e.g. "PT1H30M", "P1D" or "-P1W"
+pub fn parse_duration(value: &str) -> Option<chrono::Duration> {
+    let (sign, rest) = match value.strip_prefix('-') {
+        Some(r) => (-1, r),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let rest = rest.strip_prefix('P')?;
+    let mut secs = 0i64;
+    let mut n = String::new();
+    for c in rest.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                n.push(c);
+                continue;
+            }
+            'T' => continue,
+            'W' => 7 * 86400,
+            'D' => 86400,
+            'H' => 3600,
+            'M' => 60,
+            'S' => 1,
+            _ => return None,
+        };
+        secs += n.parse::<i64>().ok()? * unit;
+        n.clear();
+    }
+    Some(chrono::Duration::seconds(sign * secs))
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    Some(match s {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+// e.g. "FREQ=MONTHLY;INTERVAL=2;BYDAY=-1FR;UNTIL=20261231T000000Z"
+pub fn parse_rrule(value: &str) -> Option<Rule> {
+    let mut rule = Rule {
+        freq: Freq::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        by_day: Vec::new(),
+        by_month_day: Vec::new(),
+        by_month: Vec::new(),
+    };
+    let mut freq = None;
+    for part in value.split(';') {
+        let (k, v) = match part.split_once('=') {
+            Some(kv) => kv,
+            None => continue,
+        };
+        match k {
+            "FREQ" => freq = Some(match v {
+                "DAILY" => Freq::Daily,
+                "WEEKLY" => Freq::Weekly,
+                "MONTHLY" => Freq::Monthly,
+                "YEARLY" => Freq::Yearly,
+                _ => return None,
+            }),
+            "INTERVAL" => rule.interval = v.parse().ok()?,
+            "COUNT" => rule.count = Some(v.parse().ok()?),
+            "UNTIL" => {
+                let (t, is_date) = parse_datetime(v)?;
+                // a date includes instances on that day
+                rule.until = Some(if is_date { t.date().and_hms(23, 59, 59) } else { t });
+            }
+            "BYDAY" => {
+                for d in v.split(',') {
+                    let (n, wd) = d.split_at(d.len().saturating_sub(2));
+                    let n = if n.is_empty() { 0 } else { n.parse().ok()? };
+                    rule.by_day.push((n, parse_weekday(wd)?));
+                }
+            }
+            "BYMONTHDAY" => {
+                rule.by_month_day = v.split(',').map(|d| d.parse().ok()).collect::<Option<_>>()?;
+            }
+            "BYMONTH" => {
+                rule.by_month = v.split(',').map(|m| m.parse().ok()).collect::<Option<_>>()?;
+            }
+            _ => {}
+        }
+    }
+    rule.freq = freq?;
+    rule.interval = rule.interval.max(1);
+    Some(rule)
+}
+
+impl Observance {
+    // latest onset at or before t. COUNT is ignored, zones end their rules
+    // with UNTIL.
+    fn last_onset(&self, t: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut last = self.rdates.iter()
+            .chain(Some(&self.start))
+            .filter(|o| **o <= t)
+            .max()
+            .cloned();
+        if let Some(rule) = &self.rule {
+            let first = self.start.date();
+            // observances repeat at least yearly, a year back is enough
+            let mut d = t.date();
+            while d >= first && (t.date() - d).num_days() <= 366 {
+                let onset = d.and_time(self.start.time());
+                if onset <= t && rule.matches(first, d) && rule.until.is_none_or(|u| onset <= u) {
+                    last = last.max(Some(onset));
+                    break;
+                }
+                d = d.pred();
+            }
+        }
+        last
+    }
+}
+
+impl Zone {
+    // UTC offset in seconds at wall time t
+    pub fn offset_at(&self, t: NaiveDateTime) -> i64 {
+        let current = self.observances.iter()
+            .filter_map(|o| Some((o.last_onset(t)?, o)))
+            .max_by_key(|(onset, _)| *onset);
+        match current {
+            Some((_, o)) => o.offset_to,
+            // before the first onset
+            None => self.observances.iter()
+                .min_by_key(|o| o.start)
+                .map_or(0, |o| o.offset_from),
+        }
+    }
+
+    // wall time t in the zone as local time
+    pub fn to_local(&self, t: NaiveDateTime) -> NaiveDateTime {
+        let utc = t - chrono::Duration::seconds(self.offset_at(t));
+        Utc.from_utc_datetime(&utc).with_timezone(&Local).naive_local()
+    }
+
+    // UTC time as wall time in the zone
+    fn wall_time(&self, utc: NaiveDateTime) -> NaiveDateTime {
+        let guess = utc + chrono::Duration::seconds(self.offset_at(utc));
+        utc + chrono::Duration::seconds(self.offset_at(guess))
+    }
+}
+
+// the VTIMEZONEs of a calendar
+fn parse_zones(lines: &[String]) -> Vec<Zone> {
+    let mut zones = Vec::new();
+    let mut zone: Option<Zone> = None;
+    let mut observance: Option<Observance> = None;
+
+    for line in lines {
+        let (name, _, value) = match parse_property(line) {
+            Some(p) => p,
+            None => continue,
+        };
+        match (name.as_str(), value.as_str()) {
+            ("BEGIN", "VTIMEZONE") => {
+                zone = Some(Zone {
+                    id: String::new(),
+                    observances: Vec::new(),
+                });
+            }
+            ("END", "VTIMEZONE") => zones.extend(zone.take()),
+            ("BEGIN", "STANDARD") | ("BEGIN", "DAYLIGHT") if zone.is_some() => {
+                observance = Some(Observance {
+                    start: NaiveDate::from_ymd(1970, 1, 1).and_hms(0, 0, 0),
+                    rule: None,
+                    rdates: Vec::new(),
+                    offset_from: 0,
+                    offset_to: 0,
+                });
+            }
+            ("END", "STANDARD") | ("END", "DAYLIGHT") => {
+                if let (Some(z), Some(o)) = (zone.as_mut(), observance.take()) {
+                    z.observances.push(o);
+                }
+            }
+            _ => {}
+        }
+        match (observance.as_mut(), zone.as_mut(), name.as_str()) {
+            (Some(o), _, "DTSTART") => {
+                if let Some((t, _)) = parse_datetime(&value) {
+                    o.start = t;
+                }
+            }
+            (Some(o), _, "RRULE") => o.rule = parse_rrule(&value),
+            (Some(o), _, "RDATE") => {
+                o.rdates.extend(value.split(',').filter_map(parse_datetime).map(|(t, _)| t));
+            }
+            (Some(o), _, "TZOFFSETFROM") => o.offset_from = parse_offset(&value).unwrap_or(0),
+            (Some(o), _, "TZOFFSETTO") => o.offset_to = parse_offset(&value).unwrap_or(0),
+            (None, Some(z), "TZID") => z.id = value,
+            _ => {}
+        }
+    }
+    zones
+}
+
+// DATE-TIME value if it's given in UTC
+fn parse_utc(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.strip_suffix('Z')?, "%Y%m%dT%H%M%S").ok()
+}
+
+// UNTIL of an RRULE if it's given in UTC
+fn rrule_until_utc(value: &str) -> Option<NaiveDateTime> {
+    parse_utc(value.split(';').find_map(|p| p.strip_prefix("UNTIL="))?)
+}
+
+// all VEVENTs of a calendar, VALARMs and other components inside them
+// are skipped. Times with a TZID of a VTIMEZONE in the calendar keep that
+// zone, unknown zones are taken as local.
+pub fn parse_calendar(s: &str) -> Vec<Event> {
+    let lines = unfold(s);
+    let zones = parse_zones(&lines);
+    // zone of a DATE-TIME value in local or UTC time
+    let zone = |params: &Params, value: &str| -> Option<Zone> {
+        if value.len() == 8 || value.ends_with('Z') {
+            return None;
+        }
+        let (_, id) = params.iter().find(|(k, _)| k == "TZID")?;
+        zones.iter().find(|z| z.id == *id).cloned()
+    };
+    let absolute = |t: NaiveDateTime, tz: &Option<Zone>| match tz {
+        Some(z) => z.to_local(t),
+        None => t,
+    };
+
+    let mut events = Vec::new();
+    let mut event: Option<Event> = None;
+    let mut end: Option<(NaiveDateTime, Option<Zone>)> = None;
+    let mut rrule = String::new();
+    let mut exdates: Vec<String> = Vec::new();
+    let mut recurrence_id: Option<String> = None;
+    let mut nested = 0;
+
+    for line in lines {
+        let (name, params, value) = match parse_property(&line) {
+            Some(p) => p,
+            None => continue,
+        };
+        match (name.as_str(), value.as_str()) {
+            ("BEGIN", "VEVENT") => {
+                event = Some(Event {
+                    uid: String::new(),
+                    summary: String::new(),
+                    start: NaiveDate::from_ymd(1970, 1, 1).and_hms(0, 0, 0),
+                    tz: None,
+                    duration: chrono::Duration::zero(),
+                    all_day: false,
+                    rule: None,
+                    exdates: Vec::new(),
+                    recurrence_id: None,
+                    cancelled: false,
+                });
+                end = None;
+                rrule.clear();
+                exdates.clear();
+                recurrence_id = None;
+                nested = 0;
+                continue;
+            }
+            ("END", "VEVENT") => {
+                if let Some(mut e) = event.take() {
+                    if let Some((end, end_tz)) = &end {
+                        e.duration = absolute(*end, end_tz) - absolute(e.start, &e.tz);
+                    } else if e.all_day && e.duration == chrono::Duration::zero() {
+                        e.duration = chrono::Duration::days(1);
+                    }
+                    // UNTIL is in UTC for events with a zone
+                    if let (Some(z), Some(r)) = (&e.tz, e.rule.as_mut()) {
+                        if let Some(u) = rrule_until_utc(&rrule) {
+                            r.until = Some(z.wall_time(u));
+                        }
+                    }
+                    // so are EXDATE and RECURRENCE-ID, if they're given in UTC.
+                    // They're compared with wall times of the event's zone.
+                    let tz = e.tz.clone();
+                    let time = |v: &str| match (&tz, parse_utc(v)) {
+                        (Some(z), Some(u)) => Some(z.wall_time(u)),
+                        _ => parse_datetime(v).map(|(t, _)| t),
+                    };
+                    e.exdates = exdates.iter().filter_map(|v| time(v)).collect();
+                    e.recurrence_id = recurrence_id.as_deref().and_then(time);
+                    events.push(e);
+                }
+                continue;
+            }
+            ("BEGIN", _) => nested += 1,
+            ("END", _) => nested -= 1,
+            _ => {}
+        }
+        let e = match event.as_mut() {
+            Some(e) if nested == 0 => e,
+            _ => continue,
+        };
+        let is_date = params.iter().any(|(k, v)| k == "VALUE" && v == "DATE");
+        match name.as_str() {
+            "UID" => e.uid = value,
+            "SUMMARY" => e.summary = unescape(&value),
+            "DTSTART" => {
+                if let Some((t, date)) = parse_datetime(&value) {
+                    e.start = t;
+                    e.tz = zone(&params, &value);
+                    e.all_day = date || is_date;
+                }
+            }
+            "DTEND" => end = parse_datetime(&value).map(|(t, _)| (t, zone(&params, &value))),
+            "DURATION" => e.duration = parse_duration(&value).unwrap_or_else(chrono::Duration::zero),
+            "RRULE" => {
+                e.rule = parse_rrule(&value);
+                rrule = value;
+            }
+            "EXDATE" => exdates.extend(value.split(',').map(String::from)),
+            "RECURRENCE-ID" => recurrence_id = Some(value),
+            "STATUS" => e.cancelled = value == "CANCELLED",
+            _ => {}
+        }
+    }
+    events
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (y, m) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    NaiveDate::from_ymd(y, m, 1).pred().day()
+}
+
+// whether day (0 based) of a period len days long is its n-th weekday of
+// its kind, counted from the end for negative n
+fn nth_in(day: u32, len: u32, n: i32) -> bool {
+    match n {
+        0 => true,
+        n if n > 0 => (day / 7 + 1) as i32 == n,
+        n => ((len - 1 - day) / 7 + 1) as i32 == -n,
+    }
+}
+
+fn month_day_matches(day: NaiveDate, n: i32) -> bool {
+    let len = days_in_month(day.year(), day.month()) as i32;
+    let d = day.day() as i32;
+    d == n || d == len + 1 + n
+}
+
+impl Rule {
+    // BYDAY and BYMONTHDAY within the month of day
+    fn in_month(&self, day: NaiveDate, start: NaiveDate) -> bool {
+        let len = days_in_month(day.year(), day.month());
+        if self.by_day.is_empty() && self.by_month_day.is_empty() {
+            return day.day() == start.day();
+        }
+        (self.by_day.is_empty() || self.by_day.iter()
+            .any(|&(n, wd)| wd == day.weekday() && nth_in(day.day0(), len, n)))
+            && (self.by_month_day.is_empty() || self.by_month_day.iter()
+                .any(|&n| month_day_matches(day, n)))
+    }
+
+    // whether the rule has an instance on day, ignoring COUNT and UNTIL
+    fn matches(&self, start: NaiveDate, day: NaiveDate) -> bool {
+        if day < start {
+            return false;
+        }
+        if !self.by_month.is_empty() && !self.by_month.contains(&day.month()) {
+            return false;
+        }
+        let interval = self.interval as i64;
+        let months = (day.year() - start.year()) as i64 * 12
+            + day.month() as i64 - start.month() as i64;
+        match self.freq {
+            Freq::Daily => {
+                (day - start).num_days() % interval == 0
+                    && (self.by_day.is_empty()
+                        || self.by_day.iter().any(|&(_, wd)| wd == day.weekday()))
+                    && (self.by_month_day.is_empty()
+                        || self.by_month_day.iter().any(|&n| month_day_matches(day, n)))
+            }
+            Freq::Weekly => {
+                let monday = |d: NaiveDate| d - chrono::Duration::days(
+                    d.weekday().num_days_from_monday() as i64);
+                let weeks = (monday(day) - monday(start)).num_days() / 7;
+                weeks % interval == 0 && if self.by_day.is_empty() {
+                    day.weekday() == start.weekday()
+                } else {
+                    self.by_day.iter().any(|&(_, wd)| wd == day.weekday())
+                }
+            }
+            Freq::Monthly => months % interval == 0 && self.in_month(day, start),
+            Freq::Yearly => {
+                let years = (day.year() - start.year()) as i64;
+                if years % interval != 0 {
+                    return false;
+                }
+                if self.by_month.is_empty() && !self.by_day.is_empty()
+                    && self.by_month_day.is_empty() {
+                    // n-th weekday of the year
+                    let len = NaiveDate::from_ymd(day.year(), 12, 31).ordinal();
+                    return self.by_day.iter().any(|&(n, wd)| {
+                        wd == day.weekday() && nth_in(day.ordinal0(), len, n)
+                    });
+                }
+                (!self.by_month.is_empty() || day.month() == start.month())
+                    && self.in_month(day, start)
+            }
+        }
+    }
+}
+
+impl Event {
+    // start of the instance on day, if there is one. Excluded instances
+    // still count towards COUNT.
+    pub fn instance_on(&self, day: NaiveDate) -> Option<NaiveDateTime> {
+        let start = day.and_time(self.start.time());
+        let rule = match &self.rule {
+            None => return Some(self.start).filter(|s| s.date() == day),
+            Some(r) => r,
+        };
+        // DTSTART is always the first instance, even if the rule says
+        // otherwise
+        let first = self.start.date();
+        let on = |d: NaiveDate| d == first || rule.matches(first, d);
+        if day < first || !on(day) || rule.until.is_some_and(|u| start > u) {
+            return None;
+        }
+        if let Some(count) = rule.count {
+            let mut n = 0;
+            let mut d = first;
+            while d < day {
+                n += on(d) as u32;
+                if n >= count {
+                    return None;
+                }
+                d = d.succ();
+            }
+        }
+        Some(start).filter(|s| !self.exdates.contains(s))
+    }
+
+    // wall time of the event as local time
+    fn local(&self, t: NaiveDateTime) -> NaiveDateTime {
+        match &self.tz {
+            Some(z) => z.to_local(t),
+            None => t,
+        }
+    }
+}
+
+// timed events on day, sorted by start
+pub fn instances_on(events: &[Event], day: NaiveDate) -> Vec<Instance> {
+    let mut instances: Vec<Instance> = events.iter()
+        .filter(|e| !e.all_day && !e.cancelled)
+        .flat_map(|e| {
+            // the zone's day may start on the day before or after ours
+            let days = match e.tz {
+                Some(_) => vec![day.pred(), day, day.succ()],
+                None => vec![day],
+            };
+            days.into_iter().filter_map(move |d| {
+                let start = if e.recurrence_id.is_some() {
+                    Some(e.start).filter(|s| s.date() == d)
+                } else {
+                    // moved or cancelled instances have their own VEVENT
+                    e.instance_on(d).filter(|s| !events.iter().any(|o| {
+                        o.uid == e.uid && o.recurrence_id == Some(*s)
+                    }))
+                };
+                let start = e.local(start?);
+                Some(Instance {
+                    summary: e.summary.clone(),
+                    start,
+                    end: start + e.duration,
+                })
+            })
+        })
+        .filter(|i| i.start.date() == day)
+        .collect();
+    instances.sort_by_key(|i| i.start);
+    instances
+}
+
+// .ics files in paths and their subdirectories, and the directories to watch
+fn calendar_files(paths: &[PathBuf]) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    let mut todo: Vec<PathBuf> = paths.to_vec();
+    while let Some(p) = todo.pop() {
+        if p.is_dir() {
+            todo.extend(std::fs::read_dir(&p)
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .map(|e| e.path()));
+            dirs.push(p);
+        } else if p.extension().is_some_and(|e| e == "ics") {
+            files.push(p);
+        }
+    }
+    (files, dirs)
+}
+
+fn load_events(files: &[PathBuf]) -> Vec<Event> {
+    files.iter()
+        .filter_map(|f| std::fs::read_to_string(f).ok())
+        .flat_map(|s| parse_calendar(&s))
+        .collect()
+}
+
+fn add_watch(fd: i32, path: &Path) {
+    let path = CString::new(path.as_os_str().as_encoded_bytes()).unwrap_or_default();
+    let mask = libc::IN_CLOSE_WRITE | libc::IN_CREATE | libc::IN_DELETE
+        | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+    unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) };
+}
+
+impl Agenda {
+    pub fn new(paths: Vec<&'static str>, format: &'static str) -> Agenda {
+        Agenda {
+            paths: paths.iter().map(|p| expand_home(p)).collect(),
+            format,
+            state: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // the next event and the seconds until it, rounded up to full minutes:
+    // an event at 10:00 is "1m" away at 9:59:30
+    fn next(instances: &[Instance]) -> Option<(&Instance, u64)> {
+        let now = Local::now().naive_local();
+        let next = instances.iter().find(|i| i.start > now)?;
+        Some((next, ((next.start - now).num_seconds() as u64).div_ceil(60) * 60))
+    }
+}
+
+impl BarModule for Agenda {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let state = self.state.lock().unwrap();
+        let (next, secs) = match Self::next(&state) {
+            Some(n) => n,
+            None => return align,
+        };
+        let urgent = secs <= AGENDA_URGENT;
+        // blinks like a tag with an urgent window
+        let color_box = if !urgent {
+            COLOR_BG_AGENDA
+        } else if animation::urgent_blink() {
+            COLOR_URGENT_WINDOW
+        } else {
+            COLOR_URGENT_WINDOW_BLINK
+        };
+
+        let b = CairoTextBox {
+            text: format_placeholders(self.format, &[
+                ("summary", next.summary.clone()),
+                ("time", next.start.format("%H:%M").to_string()),
+                ("countdown", compact_duration(secs)),
+            ]),
+            height: dyn_config.height,
+            color_text: if urgent { COLOR_TEXT_URGENT_WINDOW } else { COLOR_TEXT },
+            color_box,
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn animation(&self) -> Option<Duration> {
+        let urgent = Self::next(&self.state.lock().unwrap())
+            .is_some_and(|(_, secs)| secs <= AGENDA_URGENT);
+        if urgent {
+            Some(animation::until_urgent_blink())
+        } else {
+            None
+        }
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        let paths = self.paths.clone();
+        thread::spawn(move || {
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+            let inotify = if fd < 0 { None } else { Some(unsafe { File::from_raw_fd(fd) }) };
+            let mut buf = [0u8; 4096];
+            let mut events = Vec::new();
+            let mut changed = true;
+            loop {
+                if changed {
+                    let (files, dirs) = calendar_files(&paths);
+                    if let Some(f) = inotify.as_ref() {
+                        // new calendars are picked up, watching twice is fine
+                        for p in dirs.iter().chain(files.iter()) {
+                            add_watch(f.as_raw_fd(), p);
+                        }
+                    }
+                    events = load_events(&files);
+                }
+                // expanded every time, the day may have changed
+                *state.lock().unwrap() = instances_on(&events, Local::today().naive_local());
+                signal_bar_redraw(bar_state.clone());
+
+                // the countdown changes every full minute
+                let now = Local::now().time();
+                let ms = 60000 - (now.second() * 1000 + now.nanosecond() / 1000000) as i64;
+                changed = match inotify.as_ref() {
+                    Some(mut f) => {
+                        let mut pfd = libc::pollfd {
+                            fd: f.as_raw_fd(),
+                            events: libc::POLLIN,
+                            revents: 0,
+                        };
+                        let ready = unsafe { libc::poll(&mut pfd, 1, ms as i32) } > 0;
+                        if ready {
+                            // editors and syncs write in bursts
+                            thread::sleep(Duration::from_millis(100));
+                            while unsafe { libc::poll(&mut pfd, 1, 0) } > 0 {
+                                let _ = f.read(&mut buf);
+                            }
+                        }
+                        ready
+                    }
+                    None => {
+                        thread::sleep(Duration::from_millis(ms as u64));
+                        true
+                    }
+                };
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const CALENDAR: &str = include_str!("../../tests/fixtures/calendar.ics");
+    const WORK: &str = include_str!("../../tests/fixtures/work.ics");
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    fn event(uid: &str) -> Event {
+        parse_calendar(CALENDAR).into_iter()
+            .find(|e| e.uid == uid && e.recurrence_id.is_none())
+            .unwrap()
+    }
+
+    fn utc_to_local(t: NaiveDateTime) -> NaiveDateTime {
+        Utc.from_utc_datetime(&t).with_timezone(&Local).naive_local()
+    }
+
+    #[test]
+    fn rrule() {
+        assert_eq!(parse_rrule("FREQ=MONTHLY;INTERVAL=2;BYDAY=-1FR,2MO,TU;BYMONTHDAY=1,-1;\
+                                BYMONTH=1,7;COUNT=4"), Some(Rule {
+            freq: Freq::Monthly,
+            interval: 2,
+            count: Some(4),
+            until: None,
+            by_day: vec![(-1, Weekday::Fri), (2, Weekday::Mon), (0, Weekday::Tue)],
+            by_month_day: vec![1, -1],
+            by_month: vec![1, 7],
+        }));
+        // a date includes its day
+        let r = parse_rrule("FREQ=DAILY;UNTIL=20201231;INTERVAL=0").unwrap();
+        assert_eq!(r.until, Some(date(2020, 12, 31).and_hms(23, 59, 59)));
+        assert_eq!(r.interval, 1);
+    }
+
+    #[test]
+    fn rrule_invalid() {
+        assert_eq!(parse_rrule("INTERVAL=2"), None);
+        assert_eq!(parse_rrule("FREQ=HOURLY"), None);
+        assert_eq!(parse_rrule("FREQ=WEEKLY;BYDAY=XX"), None);
+        assert_eq!(parse_rrule("FREQ=WEEKLY;COUNT=many"), None);
+    }
+
+    #[test]
+    fn instance_count() {
+        let e = event("standup");
+        let days: Vec<u32> = (1..=31)
+            .filter(|d| e.instance_on(date(2020, 10, *d)).is_some())
+            .collect();
+        assert_eq!(days, vec![5, 7, 9, 12, 14]);
+        assert_eq!(e.instance_on(date(2020, 10, 12)), Some(date(2020, 10, 12).and_hms(9, 30, 0)));
+    }
+
+    #[test]
+    fn instance_last_friday_until_exdate() {
+        let e = event("review");
+        assert_eq!(e.summary, "Monthly review");
+        assert_eq!(e.duration, chrono::Duration::hours(1));
+        let on = |y, m, d| e.instance_on(date(y, m, d)).is_some();
+        assert!(on(2020, 10, 30));
+        // excluded
+        assert!(!on(2020, 11, 27));
+        assert!(on(2020, 12, 25));
+        assert!(on(2021, 1, 29));
+        // past UNTIL
+        assert!(!on(2021, 2, 26));
+        // not the last friday
+        assert!(!on(2020, 12, 18));
+        assert!(!on(2020, 10, 23));
+    }
+
+    #[test]
+    fn instances_with_overrides() {
+        let events = parse_calendar(CALENDAR);
+        let summaries = |d| -> Vec<(String, NaiveDateTime)> {
+            instances_on(&events, d).into_iter()
+                .filter(|i| i.summary != "Call")
+                .map(|i| (i.summary, i.start))
+                .collect()
+        };
+        // the holiday is all day
+        assert_eq!(summaries(date(2020, 10, 12)),
+                   vec![(String::from("Standup"), date(2020, 10, 12).and_hms(9, 30, 0))]);
+        // moved
+        assert_eq!(summaries(date(2020, 10, 13)),
+                   vec![(String::from("Gym, late"), date(2020, 10, 13).and_hms(20, 0, 0))]);
+        // cancelled
+        assert!(summaries(date(2020, 10, 20)).is_empty());
+        assert_eq!(summaries(date(2020, 10, 27)),
+                   vec![(String::from("Gym"), date(2020, 10, 27).and_hms(18, 0, 0))]);
+        let late = instances_on(&events, date(2020, 10, 13));
+        let late = late.iter().find(|i| i.summary == "Gym, late").unwrap();
+        assert_eq!(late.end, date(2020, 10, 13).and_hms(21, 0, 0));
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse_offset("+0100"), Some(3600));
+        assert_eq!(parse_offset("-0500"), Some(-18000));
+        assert_eq!(parse_offset("+053000"), Some(19800));
+        assert_eq!(parse_offset("0100"), None);
+        assert_eq!(parse_offset("+01"), None);
+    }
+
+    #[test]
+    fn zone_offsets() {
+        let tz = event("call").tz.unwrap();
+        assert_eq!(tz.id, "America/New_York");
+        let at = |y, m, d, h, min| tz.offset_at(date(y, m, d).and_hms(h, min, 0));
+        assert_eq!(at(2020, 7, 1, 12, 0), -4 * 3600);
+        assert_eq!(at(2020, 12, 1, 12, 0), -5 * 3600);
+        // around the switch on the second sunday of march
+        assert_eq!(at(2020, 3, 8, 1, 30), -5 * 3600);
+        assert_eq!(at(2020, 3, 8, 3, 30), -4 * 3600);
+        // and back on the first sunday of november
+        assert_eq!(at(2020, 11, 1, 0, 30), -4 * 3600);
+        assert_eq!(at(2020, 11, 1, 2, 30), -5 * 3600);
+        // before the first onset
+        assert_eq!(at(1969, 7, 1, 12, 0), -5 * 3600);
+    }
+
+    #[test]
+    fn instances_in_zone() {
+        let events = parse_calendar(CALENDAR);
+        let call = |start: NaiveDateTime| {
+            instances_on(&events, start.date()).into_iter()
+                .find(|i| i.summary == "Call")
+                .map(|i| (i.start, i.end))
+        };
+        // 8:00 EDT, and 8:00 EST after the switch
+        let edt = utc_to_local(date(2020, 10, 26).and_hms(12, 0, 0));
+        assert_eq!(call(edt), Some((edt, edt + chrono::Duration::minutes(30))));
+        let est = utc_to_local(date(2020, 11, 2).and_hms(13, 0, 0));
+        assert_eq!(call(est), Some((est, est + chrono::Duration::minutes(30))));
+        // UNTIL is 8:00 EST on the 9th
+        let last = utc_to_local(date(2020, 11, 9).and_hms(13, 0, 0));
+        assert!(call(last).is_some());
+        let past = utc_to_local(date(2020, 11, 16).and_hms(13, 0, 0));
+        assert_eq!(call(past), None);
+    }
+
+    #[test]
+    fn utc_exceptions_in_zone() {
+        let events = parse_calendar(CALENDAR);
+        let calls = |d| -> Vec<NaiveDateTime> {
+            instances_on(&events, d).into_iter()
+                .filter(|i| i.summary == "Call")
+                .map(|i| i.start)
+                .collect()
+        };
+        // EXDATE and RECURRENCE-ID are 8:00 EDT in UTC
+        let excluded = utc_to_local(date(2020, 10, 12).and_hms(12, 0, 0));
+        assert!(calls(excluded.date()).is_empty());
+        let moved = utc_to_local(date(2020, 10, 19).and_hms(14, 0, 0));
+        assert_eq!(calls(moved.date()), vec![moved]);
+        let e = events.iter().find(|e| e.uid == "call" && e.recurrence_id.is_some()).unwrap();
+        assert_eq!(e.recurrence_id, Some(date(2020, 10, 19).and_hms(8, 0, 0)));
+    }
+
+    #[test]
+    fn unknown_zone_is_local() {
+        let e = event("elsewhere");
+        assert_eq!(e.tz, None);
+        assert_eq!(e.start, date(2020, 10, 5).and_hms(11, 0, 0));
+    }
+    #[test]
+    fn calendar_directories() {
+        let root = std::env::temp_dir()
+            .join(format!("ggbar-agenda-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("work")).unwrap();
+        fs::write(root.join("personal.ics"), CALENDAR).unwrap();
+        fs::write(root.join("work").join("work.ics"), WORK).unwrap();
+        fs::write(root.join("work").join("notes.txt"), WORK).unwrap();
+
+        let (mut files, mut dirs) = calendar_files(&[root.clone(), root.join("missing")]);
+        files.sort();
+        dirs.sort();
+        assert_eq!(files, vec![root.join("personal.ics"), root.join("work").join("work.ics")]);
+        assert_eq!(dirs, vec![root.clone(), root.join("work")]);
+
+        let events = load_events(&files);
+        let retro: Vec<_> = instances_on(&events, date(2020, 10, 16)).into_iter()
+            .map(|i| (i.summary, i.start))
+            .collect();
+        assert_eq!(retro, vec![(String::from("Retro"), date(2020, 10, 16).and_hms(15, 0, 0))]);
+        assert!(events.iter().any(|e| e.uid == "standup"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use crate::utils;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use super::{BarModule, Click, SCROLL_UP, SCROLL_DOWN};
+
+const BACKLIGHT_ROOT: &str = "/sys/class/backlight";
+
+// brightness keys handled by the firmware change the brightness without an
+// inotify event, so it's re-read at least this often (ms)
+const FALLBACK_POLL: i32 = 5000;
+
+pub struct Backlight {
+    // directory in /sys/class/backlight, empty if there is none
+    dir: PathBuf,
+    // percent per scroll step
+    step: u32,
+    // placeholders: {percent}
+    format: &'static str,
+    bar: bool,
+    state: Arc<Mutex<Option<Brightness>>>,
+    // raw brightness values for the setter thread
+    setter: Sender<u64>,
+    setter_rx: Mutex<Option<Receiver<u64>>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brightness {
+    pub current: u64,
+    pub max: u64,
+}
+
+impl Brightness {
+    pub fn percent(&self) -> f64 {
+        if self.max == 0 {
+            return 0.0;
+        }
+        100.0 * self.current as f64 / self.max as f64
+    }
+
+    // raw value after changing the brightness by `step` percent. Moves by at
+    // least one unit and never turns the backlight off completely.
+    pub fn stepped(&self, step: i32) -> u64 {
+        let p = (self.percent().round() as i64 + step as i64).clamp(0, 100);
+        let mut value = (p as u64 * self.max + 50) / 100;
+        if step > 0 && value <= self.current {
+            value = self.current + 1;
+        } else if step < 0 && value >= self.current {
+            value = self.current.saturating_sub(1);
+        }
+        value.clamp(1.min(self.max), self.max)
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse::<u64>().ok()
+}
+
+pub fn read_brightness(dir: &Path) -> Option<Brightness> {
+    Some(Brightness {
+        current: read_u64(&dir.join("brightness"))?,
+        max: read_u64(&dir.join("max_brightness"))?,
+    })
+}
+
+// first backlight device in alphabetical order
+fn first_device(root: &Path) -> Option<PathBuf> {
+    let mut devices: Vec<PathBuf> = std::fs::read_dir(root)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect();
+    devices.sort();
+    devices.into_iter().next()
+}
+
+fn is_writable(path: &Path) -> bool {
+    match CString::new(path.as_os_str().as_encoded_bytes()) {
+        Ok(p) => unsafe { libc::access(p.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+// writes the brightness file directly when we may, otherwise asks logind,
+// which allows it for the active session without root
+fn set_brightness(dbus: &mut Option<dbus::blocking::Connection>, dir: &Path,
+                  value: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let file = dir.join("brightness");
+    if is_writable(&file) {
+        std::fs::write(file, value.to_string())?;
+        return Ok(());
+    }
+
+    if dbus.is_none() {
+        *dbus = Some(dbus::blocking::Connection::new_system()?);
+    }
+    let device = dir.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let proxy = dbus.as_ref().unwrap().with_proxy("org.freedesktop.login1",
+                                                  "/org/freedesktop/login1/session/auto",
+                                                  Duration::from_millis(1000));
+    proxy.method_call::<(), _, _, _>("org.freedesktop.login1.Session", "SetBrightness",
+                                     ("backlight", device, value as u32))?;
+    Ok(())
+}
+
+impl Backlight {
+    // None for the first device in /sys/class/backlight
+    pub fn new(device: Option<&'static str>, step: u32, format: &'static str,
+               bar: bool) -> Backlight {
+        let root = Path::new(BACKLIGHT_ROOT);
+        let dir = match device {
+            Some(d) => root.join(d),
+            None => first_device(root).unwrap_or_default(),
+        };
+        let (setter, setter_rx) = mpsc::channel();
+        Backlight {
+            dir,
+            step,
+            format,
+            bar,
+            state: Arc::new(Mutex::new(None)),
+            setter,
+            setter_rx: Mutex::new(Some(setter_rx)),
+        }
+    }
+}
+
+impl BarModule for Backlight {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let brightness = match *self.state.lock().unwrap() {
+            Some(b) => b,
+            None => return align,
+        };
+
+        let b = CairoTextBox {
+            text: format_placeholders(self.format, &[
+                ("percent", format!("{:.0}", brightness.percent())),
+            ]),
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box: COLOR_BG_BACKLIGHT,
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        if !self.bar {
+            return b.draw(cairo, dyn_config.font.clone());
+        }
+
+        // horizontal bar filled up to the current brightness, after the text
+        let (left, _, room_left) = b.draw_with_room(cairo, dyn_config.font.clone(),
+                                                    0.0, BACKLIGHT_BAR_WIDTH + BLOCK_MARGIN);
+        let bar_h = BACKLIGHT_BAR_HEIGHT * dyn_config.height;
+        let bar_left = room_left + BLOCK_MARGIN;
+        let bar_top = 0.5 * (dyn_config.height - bar_h);
+        utils::cairo_source_rgb_hex(cairo, 0xff000000);
+        cairo.rectangle(bar_left, bar_top, BACKLIGHT_BAR_WIDTH, bar_h);
+        cairo.fill();
+        utils::cairo_source_rgb_hex(cairo, COLOR_BACKLIGHT_BAR);
+        cairo.rectangle(bar_left, bar_top, BACKLIGHT_BAR_WIDTH * brightness.percent() / 100.0,
+                        bar_h);
+        cairo.fill();
+        left
+    }
+
+    fn click(&self, _conn: &xcb::Connection, click: &Click) -> bool {
+        let step = match click.button {
+            SCROLL_UP => self.step as i32,
+            SCROLL_DOWN => -(self.step as i32),
+            _ => return false,
+        };
+        // shown right away, so fast scrolling keeps stepping from the new
+        // value before the kernel reports it back
+        let mut state = self.state.lock().unwrap();
+        match state.as_mut() {
+            Some(b) => {
+                b.current = b.stepped(step);
+                let _ = self.setter.send(b.current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        if let Some(rx) = self.setter_rx.lock().unwrap().take() {
+            let dir = self.dir.clone();
+            thread::spawn(move || {
+                let mut dbus = None;
+                for value in rx {
+                    // reconnect on the next step, the bus may have restarted
+                    if set_brightness(&mut dbus, &dir, value).is_err() {
+                        dbus = None;
+                    }
+                }
+            });
+        }
+
+        let state = self.state.clone();
+        let dir = self.dir.clone();
+        thread::spawn(move || {
+            let inotify = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+            let inotify = if inotify >= 0 {
+                Some(unsafe { File::from_raw_fd(inotify) })
+            } else {
+                None
+            };
+            if let Some(f) = &inotify {
+                let path = CString::new(dir.join("brightness").as_os_str().as_encoded_bytes())
+                    .unwrap_or_default();
+                unsafe { libc::inotify_add_watch(f.as_raw_fd(), path.as_ptr(), libc::IN_MODIFY) };
+            }
+
+            let mut buf = [0u8; 4096];
+            loop {
+                *state.lock().unwrap() = read_brightness(&dir);
+                signal_bar_redraw(bar_state.clone());
+
+                match inotify.as_ref() {
+                    Some(mut f) => {
+                        let mut pfd = libc::pollfd {
+                            fd: f.as_raw_fd(),
+                            events: libc::POLLIN,
+                            revents: 0,
+                        };
+                        if unsafe { libc::poll(&mut pfd, 1, FALLBACK_POLL) } > 0 {
+                            let _ = f.read(&mut buf);
+                        }
+                    }
+                    None => thread::sleep(Duration::from_millis(FALLBACK_POLL as u64)),
+                }
+            }
+        });
+    }
+}
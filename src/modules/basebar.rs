@@ -8,9 +8,23 @@ use super::BarModule;
 pub struct BaseBar {}
 
 impl BarModule for BaseBar {
-    fn render(&self, _dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
-        utils::cairo_source_rgb_hex(cairo, COLOR_BG);
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        if !dyn_config.transparent {
+            // the alpha channel would only darken the color
+            utils::cairo_source_rgb_hex(cairo, COLOR_BG | 0xff000000);
+            cairo.paint();
+            return align;
+        }
+
+        // around the rounded corners the windows below show through
+        cairo.save();
+        cairo.set_operator(cairo::Operator::Clear);
         cairo.paint();
+        cairo.restore();
+        utils::cairo_source_rgb_hex(cairo, COLOR_BG);
+        utils::cairo_rounded_rectangle(cairo, 0.0, 0.0, dyn_config.width, dyn_config.height,
+                                       BAR_RADIUS);
+        cairo.fill();
         align
     }
 
@@ -3,90 +3,140 @@ use crate::BarState;
 use std::time::Duration;
 use std::thread;
 use std::sync::{Arc, Mutex, Condvar};
-use std::fs::File;
 use crate::config::*;
-use std::io::{BufRead, BufReader};
 use crate::utils;
 use super::BarModule;
 use crate::utils::*;
+use crate::animation;
 
 pub struct Battery {
-    pub dirs: Vec<String>,
+    dirs: Vec<String>,
+    // same order as dirs, None if a battery can't be read
+    state: Arc<Mutex<Vec<Option<Level>>>>,
+}
+
+#[derive(Clone, Copy)]
+struct Level {
+    percent: i32,
+    discharging: bool,
+}
+
+impl Battery {
+    pub fn new(dirs: Vec<String>) -> Battery {
+        let n = dirs.len();
+        Battery {
+            dirs,
+            state: Arc::new(Mutex::new(vec![None; n])),
+        }
+    }
+}
+
+fn is_discharging(dir: &str) -> bool {
+    match std::fs::read_to_string(String::from(dir) + "status") {
+        Ok(s) => s.trim() == "Discharging",
+        Err(_) => false,
+    }
+}
+
+impl Level {
+    fn read(dir: &str) -> Option<Level> {
+        let capacity = std::fs::read_to_string(String::from(dir) + "capacity").ok()?;
+        // sysfs sometimes gives > 100 percent
+        let percent = capacity.trim().parse::<i32>().ok()?.min(100);
+        Some(Level { percent, discharging: is_discharging(dir) })
+    }
+
+    // pulses towards the urgent color
+    fn low(&self) -> bool {
+        self.percent <= BATTERY_LOW && self.discharging
+    }
 }
 
 impl BarModule for Battery {
     fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, mut align: f64) -> f64 {
-        let mut i = 0;
-        for d in &self.dirs {
-            let f = File::open(d.clone() + "capacity")
-                .expect("failed to open battery capacity file");
-            let line = BufReader::new(f).lines().next().unwrap().unwrap();
-            // sysfs sometimes gives > 100 percent
-            let percentage = std::cmp::min(line.parse::<i32>().unwrap(), 100);
+        let levels = self.state.lock().unwrap().clone();
+        for (i, level) in levels.iter().flatten().enumerate() {
+            let percentage = level.percent;
             let p = percentage as f64 / 100.0;
 
+            // pulse towards the urgent color while low and discharging
+            let color_bg = if level.low() {
+                let t = animation::pulse(Duration::from_millis(BATTERY_PULSE_PERIOD));
+                animation::fade(COLOR_BG_BATTERY, COLOR_URGENT_WINDOW, t)
+            } else {
+                COLOR_BG_BATTERY
+            };
+
             // using only half the margin from the second battery on
             // this looks slightly better
-            let margin: f64;
-            if i > 0 {
-                margin = BLOCK_MARGIN / 2.0;
+            let margin = if i > 0 {
+                BLOCK_MARGIN / 2.0
             } else {
-                margin = BLOCK_MARGIN;
-            }
+                BLOCK_MARGIN
+            };
 
-            // battery symbol
+            // battery symbol, in the block between its start and the text
             let bat_sym_h = 0.6 * dyn_config.height;
             let bat_sym_w = 1.25 * dyn_config.height;
             let bat_sym_margin = 3.0;
-            let bat_sym_left = align - (bat_sym_w + 2.0*bat_sym_margin) - margin;
             let bat_fill_margin = 0.15 * dyn_config.height;
 
-            // background
-            utils::cairo_source_rgb_hex(cairo, COLOR_BG_BATTERY);
-            cairo.rectangle(bat_sym_left,
-                            0.0,
-                            bat_sym_w + 2.0 * bat_sym_margin + margin,
-                            dyn_config.height);
-            cairo.fill();
+            let b = CairoTextBox {
+                text: format!("{}%", percentage),
+                height: dyn_config.height,
+                color_text: COLOR_TEXT,
+                color_box: color_bg,
+                alignment: Alignment::Right,
+                align,
+                margin: BLOCK_MARGIN,
+            };
+            let room = bat_sym_w + 2.0 * bat_sym_margin + margin;
+            let (left, room_left, _) = b.draw_with_room(cairo, dyn_config.font.clone(),
+                                                        room, 0.0);
+            // the symbol is laid out from the right end of its room
+            let align_sym = room_left + room;
+
             // battery background
-            utils::cairo_source_rgb_hex(cairo, 0x0);
-            cairo.rectangle(align - (bat_sym_w + bat_sym_margin) - margin,
+            utils::cairo_source_rgb_hex(cairo, 0xff000000);
+            cairo.rectangle(align_sym - (bat_sym_w + bat_sym_margin) - margin,
                             0.5 * (dyn_config.height - bat_sym_h),
                             bat_sym_w,
                             bat_sym_h);
             cairo.fill();
             // battery "connector piece"
-            cairo.rectangle(align - (bat_sym_margin) - margin,
+            cairo.rectangle(align_sym - (bat_sym_margin) - margin,
                             0.5 * (dyn_config.height - bat_sym_h * 0.5),
                             3.0,
                             bat_sym_h * 0.5);
             cairo.fill();
             // faded inner color
             utils::cairo_source_rgb_rgfade(cairo, p);
-            cairo.rectangle(align - (bat_sym_w + bat_sym_margin) + bat_fill_margin - margin,
+            cairo.rectangle(align_sym - (bat_sym_w + bat_sym_margin) + bat_fill_margin - margin,
                             0.5 * (dyn_config.height - bat_sym_h) + bat_fill_margin,
                             (bat_sym_w - 2.0 * bat_fill_margin) * p,
                             bat_sym_h - 2.0 * bat_fill_margin);
             cairo.fill();
-
-            let b = CairoTextBox {
-                text: format!("{}%", percentage),
-                height: dyn_config.height,
-                color_text: COLOR_TEXT,
-                color_box: COLOR_BG_BATTERY,
-                alignment: Alignment::Right,
-                align: bat_sym_left,
-                margin: BLOCK_MARGIN,
-            };
-            align = b.draw(cairo, dyn_config.clone().font);
-            i += 1;
+            align = left;
         }
         align
     }
 
+    fn animation(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        if state.iter().flatten().any(|l| l.low()) {
+            Some(Duration::from_millis(ANIMATION_FRAME))
+        } else {
+            None
+        }
+    }
+
     fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let dirs = self.dirs.clone();
+        let state = self.state.clone();
         thread::spawn(move || {
             loop {
+                // read here, animation frames only pulse the color
+                *state.lock().unwrap() = dirs.iter().map(|d| Level::read(d)).collect();
                 signal_bar_redraw(bar_state.clone());
                 thread::sleep(Duration::from_millis(60000));
             }
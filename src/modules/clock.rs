@@ -2,13 +2,162 @@ use crate::config::*;
 use crate::utils::*;
 use crate::BarState;
 use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::popup::{Popup, PopupEvent};
 use std::thread;
 use std::sync::{Arc, Mutex, Condvar};
 use std::time::Duration;
-use chrono::Local;
-use super::BarModule;
+use chrono::{Datelike, Local, NaiveDate};
+use super::{BarModule, Click, BUTTON_LEFT, BUTTON_MIDDLE, SCROLL_UP, SCROLL_DOWN};
 
-pub struct Clock {}
+const WEEKDAYS: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+
+pub struct Clock {
+    state: Mutex<ClockState>,
+}
+
+#[derive(Default)]
+struct ClockState {
+    screen_num: i32,
+    calendar: Option<Calendar>,
+}
+
+// the calendar popup, opened by clicking the clock
+struct Calendar {
+    popup: Popup,
+    font: String,
+    // shown month relative to the current one
+    months: i32,
+    // the popup is seven cells wide
+    cell_width: f64,
+    row_height: f64,
+}
+
+// (year, month) moved by n months
+pub fn shift_month(year: i32, month: u32, n: i32) -> (i32, u32) {
+    let m = year * 12 + month as i32 - 1 + n;
+    (m.div_euclid(12), m.rem_euclid(12) as u32 + 1)
+}
+
+// weeks of a month starting on Monday, days of other months are None
+pub fn month_grid(year: i32, month: u32) -> Vec<[Option<u32>; 7]> {
+    let first = NaiveDate::from_ymd(year, month, 1);
+    let (next_year, next_month) = shift_month(year, month, 1);
+    let days = NaiveDate::from_ymd(next_year, next_month, 1).pred().day();
+
+    let mut weeks = Vec::new();
+    let mut week = [None; 7];
+    let mut col = first.weekday().num_days_from_monday() as usize;
+    for day in 1..=days {
+        week[col] = Some(day);
+        col += 1;
+        if col == 7 {
+            weeks.push(week);
+            week = [None; 7];
+            col = 0;
+        }
+    }
+    if col > 0 {
+        weeks.push(week);
+    }
+    weeks
+}
+
+fn text_width(cairo: &cairo::Context, font: &str, text: &str) -> f64 {
+    let pl = setup_pango_layout(cairo, String::from(font));
+    pl.set_text(text);
+    (pl.get_size().0 / pango::SCALE) as f64
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock {
+            state: Mutex::new(Default::default()),
+        }
+    }
+
+    fn open_calendar(conn: &xcb::Connection, screen_num: i32, click: &Click)
+        -> Option<Calendar> {
+        // measure on a scratch surface, the popup's size depends on it
+        let scratch = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1)
+            .expect("failed to create ImageSurface");
+        let cr = cairo::Context::new(&scratch);
+        let font = &click.dyn_config.font;
+        let cell = text_width(&cr, font, "00") + 2.0 * CALENDAR_CELL_MARGIN;
+        let header = text_width(&cr, font, "September 0000") + 2.0 * BLOCK_MARGIN;
+        let row_height = click.dyn_config.height;
+
+        // header, weekday names and up to six weeks
+        let width = (7.0 * cell).max(header);
+        let height = 8.0 * row_height;
+        Some(Calendar {
+            popup: Popup::open(conn, screen_num, &click.dyn_config,
+                               click.x0, click.x1, width, height)?,
+            font: font.clone(),
+            months: 0,
+            cell_width: width / 7.0,
+            row_height,
+        })
+    }
+}
+
+impl Calendar {
+    fn draw(&self, conn: &xcb::Connection) {
+        let cr = self.popup.cairo();
+        let today = Local::today().naive_local();
+        let (year, month) = shift_month(today.year(), today.month(), self.months);
+        let width = 7.0 * self.cell_width;
+
+        cairo_source_rgb_hex(cr, COLOR_BG_CALENDAR);
+        cr.paint();
+
+        // month and year, centered
+        let title = format!("{}", NaiveDate::from_ymd(year, month, 1).format("%B %Y"));
+        let title_width = text_width(cr, &self.font, &title) + 2.0 * BLOCK_MARGIN;
+        let b = CairoTextBox {
+            text: title,
+            height: self.row_height,
+            color_text: COLOR_TEXT,
+            color_box: COLOR_BG_CALENDAR_HEADER,
+            alignment: Alignment::Left,
+            align: 0.5 * (width - title_width),
+            margin: BLOCK_MARGIN,
+        };
+        b.draw_flat(cr, self.font.clone());
+
+        let cell = |row: usize, col: usize, text: String, color_text: u32, color_box: u32| {
+            cr.save();
+            cr.translate(0.0, row as f64 * self.row_height);
+            let b = CairoTextBox {
+                text,
+                height: self.row_height,
+                color_text,
+                color_box,
+                alignment: Alignment::Right,
+                align: (col + 1) as f64 * self.cell_width,
+                margin: CALENDAR_CELL_MARGIN,
+            };
+            b.draw_flat(cr, self.font.clone());
+            cr.restore();
+        };
+
+        for (col, name) in WEEKDAYS.iter().enumerate() {
+            cell(1, col, String::from(*name), COLOR_TEXT_CALENDAR_WEEKDAY, COLOR_BG_CALENDAR);
+        }
+        for (row, week) in month_grid(year, month).iter().enumerate() {
+            for (col, day) in week.iter().enumerate() {
+                let day = match day {
+                    Some(d) => *d,
+                    None => continue,
+                };
+                let is_today = self.months == 0 && day == today.day();
+                let color_box = if is_today { COLOR_BG_CALENDAR_TODAY } else { COLOR_BG_CALENDAR };
+                cell(row + 2, col, day.to_string(), COLOR_TEXT, color_box);
+            }
+        }
+
+        self.popup.show(conn);
+    }
+}
 
 impl BarModule for Clock {
     fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
@@ -20,7 +169,7 @@ impl BarModule for Clock {
             color_text: COLOR_TEXT,
             color_box: COLOR_BG_CLOCK,
             alignment: Alignment::Right,
-            align: align,
+            align,
             margin: BLOCK_MARGIN,
         };
         b.draw(cairo, dyn_config.font)
@@ -34,4 +183,52 @@ impl BarModule for Clock {
             }
         });
     }
+
+    fn x_init(&self, _conn: &xcb::Connection, screen_num: i32) {
+        self.state.lock().unwrap().screen_num = screen_num;
+    }
+
+    fn x_event(&self, conn: &xcb::Connection, event: &xcb::GenericEvent) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let calendar = match state.calendar.as_mut() {
+            Some(c) => c,
+            None => return false,
+        };
+        match calendar.popup.event(event) {
+            PopupEvent::None => {}
+            PopupEvent::Expose => calendar.draw(conn),
+            PopupEvent::Button { button: BUTTON_LEFT } | PopupEvent::Dismiss => {
+                if let Some(c) = state.calendar.take() {
+                    c.popup.close(conn);
+                }
+            }
+            PopupEvent::Button { button } => {
+                match button {
+                    SCROLL_UP => calendar.months -= 1,
+                    SCROLL_DOWN => calendar.months += 1,
+                    BUTTON_MIDDLE => calendar.months = 0,
+                    _ => return false,
+                }
+                calendar.draw(conn);
+            }
+        }
+        false
+    }
+
+    fn click(&self, conn: &xcb::Connection, click: &Click) -> bool {
+        // while the calendar is open its grab gets all clicks, so this
+        // only ever opens it
+        if click.button != BUTTON_LEFT {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.calendar.is_none() {
+            // stays closed if another client holds a grab
+            state.calendar = Self::open_calendar(conn, state.screen_num, click);
+            if let Some(calendar) = &state.calendar {
+                calendar.draw(conn);
+            }
+        }
+        false
+    }
 }
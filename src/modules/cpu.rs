@@ -0,0 +1,263 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use crate::utils;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use super::BarModule;
+
+pub enum CpuGraph {
+    None,
+    // total usage of the last n samples
+    Sparkline(usize),
+    // current usage of every core
+    PerCore,
+}
+
+pub struct Cpu {
+    interval: u64,
+    graph: CpuGraph,
+    state: Arc<Mutex<CpuState>>,
+}
+
+#[derive(Default)]
+struct CpuState {
+    prev: Vec<CpuTimes>,
+    // total usage followed by the usage of each core, in [0, 1]
+    usage: Vec<f64>,
+    history: VecDeque<f64>,
+}
+
+impl CpuState {
+    fn update(&mut self, cur: Vec<CpuTimes>, history_len: usize) {
+        // nothing parsed, e.g. from an unexpected /proc/stat in a container
+        if !cur.is_empty() && self.prev.len() == cur.len() {
+            self.usage = self.prev.iter().zip(cur.iter()).map(|(p, c)| usage(p, c)).collect();
+            self.history.push_back(self.usage[0]);
+            while self.history.len() > history_len {
+                self.history.pop_front();
+            }
+        }
+        self.prev = cur;
+    }
+}
+
+// jiffies since boot of the whole system or one core
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CpuTimes {
+    pub busy: u64,
+    pub total: u64,
+}
+
+// the `cpu` line followed by the `cpuN` lines of /proc/stat
+pub fn parse_proc_stat(s: &str) -> Vec<CpuTimes> {
+    s.lines()
+        .filter(|l| l.starts_with("cpu"))
+        .filter_map(|l| {
+            let fields: Vec<u64> = l.split_whitespace()
+                .skip(1)
+                .map(|f| f.parse::<u64>().ok())
+                .collect::<Option<Vec<u64>>>()?;
+            if fields.len() < 4 {
+                return None;
+            }
+            // user nice system idle iowait irq softirq steal, guest time is
+            // already counted in user and nice
+            let total: u64 = fields.iter().take(8).sum();
+            let idle = fields[3] + fields.get(4).unwrap_or(&0);
+            Some(CpuTimes {
+                busy: total - idle,
+                total,
+            })
+        })
+        .collect()
+}
+
+// usage in [0, 1] between two samples
+pub fn usage(prev: &CpuTimes, cur: &CpuTimes) -> f64 {
+    let total = cur.total.saturating_sub(prev.total);
+    if total == 0 {
+        return 0.0;
+    }
+    let busy = cur.busy.saturating_sub(prev.busy);
+    (busy as f64 / total as f64).min(1.0)
+}
+
+impl Cpu {
+    pub fn new(interval: u64, graph: CpuGraph) -> Cpu {
+        Cpu {
+            interval,
+            graph,
+            state: Arc::new(Mutex::new(Default::default())),
+        }
+    }
+
+    fn sample(state: &Mutex<CpuState>, history_len: usize) {
+        let stat = match std::fs::read_to_string("/proc/stat") {
+            Ok(s) => s,
+            Err(_) => return,
+        };
+        state.lock().unwrap().update(parse_proc_stat(&stat), history_len);
+    }
+
+    // bars from left to right in [x, x + n * CPU_GRAPH_BAR_WIDTH]
+    fn draw_bars(cairo: &cairo::Context, dyn_config: &DynamicConfig, x: f64, values: &[f64]) {
+        let h = dyn_config.height - 2.0 * CPU_GRAPH_MARGIN;
+        for (i, v) in values.iter().enumerate() {
+            // green when idle, red under full load
+            utils::cairo_source_rgb_rgfade(cairo, 1.0 - v);
+            cairo.rectangle(x + i as f64 * CPU_GRAPH_BAR_WIDTH,
+                            CPU_GRAPH_MARGIN + h * (1.0 - v),
+                            CPU_GRAPH_BAR_WIDTH,
+                            h * v);
+            cairo.fill();
+        }
+    }
+}
+
+impl BarModule for Cpu {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let s = self.state.lock().unwrap();
+        let total = s.usage.first().cloned().unwrap_or(0.0);
+
+        let b = CairoTextBox {
+            text: format!("CPU {:.0}%", total * 100.0),
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box: COLOR_BG_CPU,
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        let values: Vec<f64> = match self.graph {
+            CpuGraph::None => return b.draw(cairo, dyn_config.font.clone()),
+            CpuGraph::Sparkline(n) => {
+                // right aligned, so the graph grows in from the right
+                let mut v = vec![0.0; n.saturating_sub(s.history.len())];
+                v.extend(s.history.iter());
+                v
+            }
+            CpuGraph::PerCore => s.usage.iter().skip(1).cloned().collect(),
+        };
+
+        // the graph is part of the block, after the text
+        let w = values.len() as f64 * CPU_GRAPH_BAR_WIDTH;
+        let (left, _, graph_left) = b.draw_with_room(cairo, dyn_config.font.clone(),
+                                                     0.0, w + BLOCK_MARGIN);
+        Self::draw_bars(cairo, &dyn_config, graph_left + BLOCK_MARGIN, &values);
+        left
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        let interval = self.interval;
+        let history_len = match self.graph {
+            CpuGraph::Sparkline(n) => n,
+            _ => 0,
+        };
+        thread::spawn(move || {
+            loop {
+                Self::sample(&state, history_len);
+                signal_bar_redraw(bar_state.clone());
+                thread::sleep(Duration::from_millis(interval));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAT_0: &str = "\
+cpu  100 0 50 800 50 0 0 0 0 0
+cpu0 60 0 30 380 30 0 0 0 0 0
+cpu1 40 0 20 420 20 0 0 0 0 0
+intr 4823 9 0 0 0
+ctxt 95237
+btime 1603000000
+processes 1234
+procs_running 1
+";
+
+    // guest time in the ninth field is already part of user
+    const STAT_1: &str = "\
+cpu  400 0 100 1400 100 0 0 0 25 0
+cpu0 350 0 70 550 30 0 0 0 25 0
+cpu1 50 0 30 850 70 0 0 0 0 0
+intr 5912 9 0 0 0
+ctxt 99873
+btime 1603000000
+processes 1240
+procs_running 2
+";
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn proc_stat() {
+        assert_eq!(parse_proc_stat(STAT_0), vec![
+            CpuTimes { busy: 150, total: 1000 },
+            CpuTimes { busy: 90, total: 500 },
+            CpuTimes { busy: 60, total: 500 },
+        ]);
+        assert_eq!(parse_proc_stat(STAT_1), vec![
+            CpuTimes { busy: 500, total: 2000 },
+            CpuTimes { busy: 420, total: 1000 },
+            CpuTimes { busy: 80, total: 1000 },
+        ]);
+    }
+
+    #[test]
+    fn proc_stat_malformed() {
+        assert!(parse_proc_stat("").is_empty());
+        assert_eq!(parse_proc_stat("cpu 1 2 3\ncpu0 1 x 3 4\ncpu1 1 2 3 4\n"),
+                   vec![CpuTimes { busy: 6, total: 10 }]);
+    }
+
+    #[test]
+    fn usage_across_samples() {
+        let prev = parse_proc_stat(STAT_0);
+        let cur = parse_proc_stat(STAT_1);
+        let u: Vec<f64> = prev.iter().zip(cur.iter()).map(|(p, c)| usage(p, c)).collect();
+        assert!(close(u[0], 0.35), "{:?}", u);
+        assert!(close(u[1], 0.66), "{:?}", u);
+        assert!(close(u[2], 0.04), "{:?}", u);
+        // the total is the mean of the cores
+        assert!(close(u[0], (u[1] + u[2]) / 2.0));
+    }
+
+    #[test]
+    fn update_history() {
+        let mut s: CpuState = Default::default();
+        s.update(parse_proc_stat(STAT_0), 2);
+        assert!(s.usage.is_empty() && s.history.is_empty());
+        s.update(parse_proc_stat(STAT_1), 2);
+        s.update(parse_proc_stat(STAT_1), 2);
+        s.update(parse_proc_stat(STAT_1), 2);
+        assert_eq!(s.usage, [0.0, 0.0, 0.0]);
+        assert_eq!(s.history.len(), 2);
+    }
+
+    #[test]
+    fn update_without_samples() {
+        let mut s: CpuState = Default::default();
+        s.update(Vec::new(), 10);
+        s.update(Vec::new(), 10);
+        assert!(s.usage.is_empty() && s.history.is_empty());
+    }
+
+    #[test]
+    fn usage_without_progress() {
+        let t = parse_proc_stat(STAT_0);
+        assert_eq!(usage(&t[0], &t[0]), 0.0);
+        // counters going backwards, after a cpu was hotplugged
+        let cur = parse_proc_stat(STAT_1);
+        assert_eq!(usage(&cur[0], &t[0]), 0.0);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use std::ffi::CString;
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use super::BarModule;
+
+#[derive(Clone)]
+pub enum Mounts {
+    // fixed list of mount points
+    Paths(Vec<&'static str>),
+    // every mount with one of these filesystem types
+    FsTypes(Vec<&'static str>),
+}
+
+pub struct Disk {
+    mounts: Mounts,
+    interval: u64,
+    // placeholders: {mount} {free} {used} {total} {percent}
+    format: &'static str,
+    // used space in percent for the warning and critical colors
+    warn_percent: f64,
+    critical_percent: f64,
+    state: Arc<Mutex<Vec<DiskUsage>>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MountEntry {
+    pub mount_point: String,
+    pub fs_type: String,
+}
+
+// sizes in bytes, `free` is what's available to unprivileged users
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiskUsage {
+    pub mount_point: String,
+    pub total: u64,
+    pub free: u64,
+    pub used: u64,
+}
+
+impl DiskUsage {
+    fn percent(&self) -> f64 {
+        let size = self.used + self.free;
+        if size == 0 {
+            return 0.0;
+        }
+        100.0 * self.used as f64 / size as f64
+    }
+}
+
+// mountinfo escapes space, tab, newline and backslash as \ooo
+fn unescape(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        let octal = b.get(i + 1..i + 4)
+            .filter(|o| b[i] == b'\\' && o.iter().all(|c| (b'0'..=b'7').contains(c)));
+        match octal {
+            Some(o) => {
+                out.push(o.iter().fold(0u8, |v, c| (v << 3) | (c - b'0')));
+                i += 4;
+            }
+            None => {
+                out.push(b[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// contents of /proc/self/mountinfo
+pub fn parse_mountinfo(s: &str) -> Vec<MountEntry> {
+    s.lines()
+        .filter_map(|l| {
+            let fields: Vec<&str> = l.split(' ').collect();
+            // optional fields end with a single "-", the fs type follows
+            let sep = fields.iter().position(|f| *f == "-")?;
+            Some(MountEntry {
+                mount_point: unescape(fields.get(4)?),
+                fs_type: String::from(*fields.get(sep + 1)?),
+            })
+        })
+        .collect()
+}
+
+fn statvfs(path: &str) -> Option<DiskUsage> {
+    let c_path = CString::new(path).ok()?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut st) } != 0 {
+        return None;
+    }
+    let frsize = st.f_frsize as u64;
+    Some(DiskUsage {
+        mount_point: String::from(path),
+        total: st.f_blocks as u64 * frsize,
+        free: st.f_bavail as u64 * frsize,
+        used: (st.f_blocks as u64 - st.f_bfree as u64) * frsize,
+    })
+}
+
+impl Disk {
+    pub fn new(mounts: Mounts, interval: u64, format: &'static str,
+               warn_percent: f64, critical_percent: f64) -> Disk {
+        Disk {
+            mounts,
+            interval,
+            format,
+            warn_percent,
+            critical_percent,
+            state: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn mount_points(mounts: &Mounts) -> Vec<String> {
+        match mounts {
+            Mounts::Paths(p) => p.iter().map(|p| String::from(*p)).collect(),
+            Mounts::FsTypes(types) => {
+                let info = std::fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+                let mut points: Vec<String> = parse_mountinfo(&info)
+                    .into_iter()
+                    .filter(|m| types.contains(&m.fs_type.as_str()))
+                    .map(|m| m.mount_point)
+                    .collect();
+                points.dedup();
+                points
+            }
+        }
+    }
+
+    fn refresh(state: &Mutex<Vec<DiskUsage>>, mounts: &Mounts) {
+        let usage = Self::mount_points(mounts)
+            .iter()
+            .filter_map(|p| statvfs(p))
+            .collect();
+        *state.lock().unwrap() = usage;
+    }
+
+    fn color(&self, d: &DiskUsage) -> u32 {
+        let p = d.percent();
+        if p >= self.critical_percent {
+            COLOR_BG_DISK_CRITICAL
+        } else if p >= self.warn_percent {
+            COLOR_BG_DISK_WARN
+        } else {
+            COLOR_BG_DISK
+        }
+    }
+}
+
+impl BarModule for Disk {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, mut align: f64) -> f64 {
+        // right aligned, the first mount ends up rightmost
+        for d in self.state.lock().unwrap().iter() {
+            let text = format_placeholders(self.format, &[
+                ("mount", d.mount_point.clone()),
+                ("free", human_bytes(d.free)),
+                ("used", human_bytes(d.used)),
+                ("total", human_bytes(d.total)),
+                ("percent", format!("{:.0}", d.percent())),
+            ]);
+            let b = CairoTextBox {
+                text,
+                height: dyn_config.height,
+                color_text: COLOR_TEXT,
+                color_box: self.color(d),
+                alignment: Alignment::Right,
+                align,
+                margin: BLOCK_MARGIN,
+            };
+            align = b.draw(cairo, dyn_config.font.clone());
+        }
+        align
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        let mounts = self.mounts.clone();
+        let interval = self.interval;
+        thread::spawn(move || {
+            // mountinfo polls with POLLPRI whenever the mount table changed
+            let mountinfo = File::open("/proc/self/mountinfo").ok();
+            loop {
+                Self::refresh(&state, &mounts);
+                signal_bar_redraw(bar_state.clone());
+
+                match &mountinfo {
+                    Some(f) => {
+                        let mut pfd = libc::pollfd {
+                            fd: f.as_raw_fd(),
+                            events: libc::POLLPRI,
+                            revents: 0,
+                        };
+                        unsafe { libc::poll(&mut pfd, 1, interval as i32) };
+                    }
+                    None => thread::sleep(std::time::Duration::from_millis(interval)),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use std::process::Command;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use super::BarModule;
+use super::herbstluftwm::{hook_stream, on_hook};
+
+// layout, frame and client state of the tag shown on each monitor
+pub struct HlwmFrame {
+    state: Arc<Mutex<FrameState>>,
+}
+
+#[derive(Default)]
+struct FrameState {
+    // monitor and tag count of the last read, the next one reads that many
+    monitors: usize,
+    tags: usize,
+    // by monitor index, read after every hook
+    frames: Vec<FrameInfo>,
+}
+
+#[derive(Debug, PartialEq)]
+struct FrameInfo {
+    algorithm: Option<String>,
+    frame_clients: Option<String>,
+    tag_clients: Option<String>,
+    floating: bool,
+    fullscreen: bool,
+}
+
+// attributes read of every tag, by index
+const TAG_ATTRS: [&str; 4] = ["name", "tiling.focused_frame.algorithm", "curframe_wcount",
+                              "client_count"];
+
+// `herbstclient chain , get_attr ...` for everything shown on n_monitors
+// monitors with n_tags tags. The counts come first, so a change of them is
+// noticed in the output.
+fn frames_command(n_monitors: usize, n_tags: usize) -> Command {
+    let mut attrs = vec![
+        String::from("monitors.count"),
+        String::from("tags.count"),
+        String::from("monitors.focus.index"),
+    ];
+    attrs.extend((0..n_monitors).map(|m| format!("monitors.{}.tag", m)));
+    for t in 0..n_tags {
+        attrs.extend(TAG_ATTRS.iter().map(|a| format!("tags.{}.{}", t, a)));
+    }
+    // these fail without a focused client, so they go last
+    attrs.push(String::from("clients.focus.floating"));
+    attrs.push(String::from("clients.focus.fullscreen"));
+
+    let mut cmd = Command::new("herbstclient");
+    cmd.arg("chain");
+    for a in attrs {
+        cmd.arg(",").arg("get_attr").arg(a);
+    }
+    cmd
+}
+
+// output of frames_command(n_monitors, n_tags), the monitor and tag count
+// and the frame of every monitor. No frames if the counts aren't the ones
+// the command was built for.
+fn parse_frames(s: &str, n_monitors: usize, n_tags: usize)
+                -> Option<(usize, usize, Vec<FrameInfo>)> {
+    let lines: Vec<&str> = s.lines().collect();
+    let monitors = lines.first()?.parse::<usize>().ok()?;
+    let tags = lines.get(1)?.parse::<usize>().ok()?;
+    if (monitors, tags) != (n_monitors, n_tags) {
+        return Some((monitors, tags, Vec::new()));
+    }
+    let focus = lines.get(2)?.parse::<usize>().ok();
+    let monitor_tags = lines.get(3..3 + n_monitors)?;
+    let tag_attrs = lines.get(3 + n_monitors..3 + n_monitors + TAG_ATTRS.len() * n_tags)?;
+    let client = &lines[3 + n_monitors + TAG_ATTRS.len() * n_tags..];
+    let client_flag = |i: usize| client.get(i) == Some(&"true");
+
+    let frames = monitor_tags.iter()
+        .enumerate()
+        .map(|(m, name)| {
+            // tag names may contain dots, so they are matched here instead
+            // of being looked up by name
+            let tag = tag_attrs.chunks(TAG_ATTRS.len()).find(|t| t[0] == *name);
+            let attr = |i: usize| tag.map(|t| t[i].to_string());
+            // the focused client is only on this monitor if the monitor
+            // is focused
+            let focused = focus == Some(m);
+            FrameInfo {
+                algorithm: attr(1),
+                frame_clients: attr(2),
+                tag_clients: attr(3),
+                floating: focused && client_flag(0),
+                fullscreen: focused && client_flag(1),
+            }
+        })
+        .collect();
+    Some((monitors, tags, frames))
+}
+
+fn refresh(state: &Mutex<FrameState>) {
+    let (mut n_monitors, mut n_tags) = {
+        let s = state.lock().unwrap();
+        (s.monitors, s.tags)
+    };
+    // the counts of the last read are usually still right, if not read
+    // again with the new ones
+    for _ in 0..3 {
+        let out = match frames_command(n_monitors, n_tags).output() {
+            Ok(o) => o,
+            Err(_) => break,
+        };
+        let (monitors, tags, frames) =
+            match parse_frames(&String::from_utf8_lossy(&out.stdout), n_monitors, n_tags) {
+                Some(f) => f,
+                None => break,
+            };
+        if (monitors, tags) == (n_monitors, n_tags) {
+            *state.lock().unwrap() = FrameState { monitors, tags, frames };
+            return;
+        }
+        n_monitors = monitors;
+        n_tags = tags;
+    }
+    state.lock().unwrap().frames.clear();
+}
+
+impl HlwmFrame {
+    pub fn new() -> HlwmFrame {
+        HlwmFrame {
+            state: Arc::new(Mutex::new(Default::default())),
+        }
+    }
+}
+
+impl FrameInfo {
+    fn text(&self) -> String {
+        let layout = match self.algorithm.as_deref() {
+            Some("vertical") => HLWM_LAYOUT_VERTICAL,
+            Some("horizontal") => HLWM_LAYOUT_HORIZONTAL,
+            Some("max") => HLWM_LAYOUT_MAX,
+            Some("grid") => HLWM_LAYOUT_GRID,
+            _ => "?",
+        };
+        let mut text = format!("{} {}/{}",
+                               layout,
+                               self.frame_clients.as_deref().unwrap_or("?"),
+                               self.tag_clients.as_deref().unwrap_or("?"));
+        if self.floating {
+            text.push(' ');
+            text.push_str(HLWM_FLOATING_SYMBOL);
+        }
+        if self.fullscreen {
+            text.push(' ');
+            text.push_str(HLWM_FULLSCREEN_SYMBOL);
+        }
+        text
+    }
+}
+
+impl BarModule for HlwmFrame {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let state = self.state.lock().unwrap();
+        let info = match state.frames.get(dyn_config.monitor as usize) {
+            Some(i) => i,
+            None => return align,
+        };
+        let b = CairoTextBox {
+            text: info.text(),
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box: COLOR_BG_HLWM_FRAME,
+            alignment: Alignment::Left,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        on_hook(Box::new(move || refresh(&state)));
+
+        // first read, the hook stream only reads after a hook
+        let state = self.state.clone();
+        let bs = bar_state.clone();
+        thread::spawn(move || {
+            refresh(&state);
+            signal_bar_redraw(bs);
+        });
+        hook_stream(bar_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(algorithm: &str, frame_clients: &str, tag_clients: &str) -> FrameInfo {
+        FrameInfo {
+            algorithm: Some(String::from(algorithm)),
+            frame_clients: Some(String::from(frame_clients)),
+            tag_clients: Some(String::from(tag_clients)),
+            floating: false,
+            fullscreen: false,
+        }
+    }
+
+    #[test]
+    fn frames_command_reads_tags_by_index() {
+        let cmd = frames_command(1, 2);
+        let args: Vec<String> = cmd.get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .filter(|a| a != "," && a != "get_attr")
+            .collect();
+        assert_eq!(args, [
+            "chain", "monitors.count", "tags.count", "monitors.focus.index",
+            "monitors.0.tag",
+            "tags.0.name", "tags.0.tiling.focused_frame.algorithm",
+            "tags.0.curframe_wcount", "tags.0.client_count",
+            "tags.1.name", "tags.1.tiling.focused_frame.algorithm",
+            "tags.1.curframe_wcount", "tags.1.client_count",
+            "clients.focus.floating", "clients.focus.fullscreen",
+        ]);
+    }
+
+    #[test]
+    fn frames_of_every_monitor() {
+        let out = "2\n3\n1\nweb\nv1.2\n\
+                   1\nmax\n0\n0\n\
+                   web\nvertical\n2\n3\n\
+                   v1.2\ngrid\n1\n4\n\
+                   true\nfalse\n";
+        let (monitors, tags, frames) = parse_frames(out, 2, 3).unwrap();
+        assert_eq!((monitors, tags), (2, 3));
+        let mut focused = frame("grid", "1", "4");
+        focused.floating = true;
+        assert_eq!(frames, [frame("vertical", "2", "3"), focused]);
+    }
+
+    #[test]
+    fn no_focused_client() {
+        let out = "1\n1\n0\nmain\nmain\nhorizontal\n0\n0\n";
+        let (_, _, frames) = parse_frames(out, 1, 1).unwrap();
+        assert_eq!(frames, [frame("horizontal", "0", "0")]);
+    }
+
+    #[test]
+    fn changed_counts_give_no_frames() {
+        let out = "1\n2\n0\nmain\nmain\nmax\n1\n1\n";
+        assert_eq!(parse_frames(out, 1, 1), Some((1, 2, Vec::new())));
+        assert_eq!(parse_frames("", 1, 1), None);
+        // cut off output
+        assert_eq!(parse_frames("1\n1\n0\nmain\nmain\n", 1, 1), None);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use std::sync::{Arc, Mutex, Condvar};
+use xcb::xkb;
+use super::{BarModule, Click, BUTTON_LEFT, BUTTON_RIGHT, SCROLL_UP, SCROLL_DOWN};
+
+pub struct Keyboard {
+    // (layout, displayed label) pairs, other layouts show their name
+    labels: Vec<(&'static str, &'static str)>,
+    // placeholders: {layout} {caps} {num}
+    format: &'static str,
+    state: Mutex<KeyboardState>,
+}
+
+#[derive(Default)]
+struct KeyboardState {
+    root: xcb::Window,
+    xkb_rules_names: xcb::Atom,
+    // first event code of the XKB extension, None if it's missing
+    xkb_event: Option<u8>,
+    layouts: Vec<String>,
+    group: u8,
+    locked_mods: u8,
+}
+
+// Num Lock is bound to Mod2 on practically every keymap
+const MOD_MASK_NUM_LOCK: u8 = xcb::MOD_MASK_2 as u8;
+const MOD_MASK_CAPS_LOCK: u8 = xcb::MOD_MASK_LOCK as u8;
+
+// layouts from the _XKB_RULES_NAMES root window property, which holds the
+// NUL separated rules, model, layout, variant and options of the keymap,
+// e.g. "evdev\0pc105\0us,de\0\0grp:alt_shift_toggle\0"
+pub fn parse_rules_names(value: &[u8]) -> Vec<String> {
+    value.split(|b| *b == 0)
+        .nth(2)
+        .map(|l| String::from_utf8_lossy(l).split(',').map(String::from).collect())
+        .unwrap_or_default()
+}
+
+impl Keyboard {
+    pub fn new(labels: Vec<(&'static str, &'static str)>, format: &'static str) -> Keyboard {
+        Keyboard {
+            labels,
+            format,
+            state: Mutex::new(Default::default()),
+        }
+    }
+
+    fn read_layouts(conn: &xcb::Connection, state: &mut KeyboardState) {
+        let reply = xcb::get_property(conn, false, state.root, state.xkb_rules_names,
+                                      xcb::ATOM_STRING, 0, 1024)
+            .get_reply();
+        state.layouts = match reply {
+            Ok(r) => parse_rules_names(r.value()),
+            Err(_) => Vec::new(),
+        };
+    }
+
+    fn read_state(conn: &xcb::Connection, state: &mut KeyboardState) {
+        if let Ok(r) = xkb::get_state(conn, xkb::ID_USE_CORE_KBD as u16).get_reply() {
+            state.group = r.group();
+            state.locked_mods = r.locked_mods();
+        }
+    }
+
+    fn label(&self, layout: &str) -> String {
+        match self.labels.iter().find(|(l, _)| *l == layout) {
+            Some((_, label)) => String::from(*label),
+            None => String::from(layout),
+        }
+    }
+}
+
+impl BarModule for Keyboard {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let state = self.state.lock().unwrap();
+        let layout = state.layouts.get(state.group as usize)
+            .map(|l| self.label(l))
+            .unwrap_or_default();
+        let caps = state.locked_mods & MOD_MASK_CAPS_LOCK != 0;
+        let num = state.locked_mods & MOD_MASK_NUM_LOCK != 0;
+
+        let b = CairoTextBox {
+            text: format_placeholders(self.format, &[
+                ("layout", layout),
+                ("caps", String::from(if caps { KEYBOARD_CAPS_LOCK_SYMBOL } else { "" })),
+                ("num", String::from(if num { KEYBOARD_NUM_LOCK_SYMBOL } else { "" })),
+            ]),
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box: if caps { COLOR_BG_KEYBOARD_CAPS_LOCK } else { COLOR_BG_KEYBOARD },
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn event_generator(&self, _bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        // no op, updates come in through x_event
+    }
+
+    fn x_init(&self, conn: &xcb::Connection, screen_num: i32) {
+        let setup = conn.get_setup();
+        let root = setup.roots().nth(screen_num as usize).unwrap().root();
+        // setxkbmap rewrites _XKB_RULES_NAMES when the layouts change
+        xcb::change_window_attributes(conn, root, &[
+            (xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)
+        ]);
+
+        let mut state = self.state.lock().unwrap();
+        state.root = root;
+        state.xkb_rules_names = intern_atom(conn, "_XKB_RULES_NAMES");
+        Self::read_layouts(conn, &mut state);
+
+        let supported = xkb::use_extension(conn, 1, 0)
+            .get_reply()
+            .is_ok_and(|r| r.supported());
+        if !supported {
+            return;
+        }
+        state.xkb_event = conn.get_extension_data(xkb::id()).map(|e| e.first_event());
+        let events = xkb::EVENT_TYPE_STATE_NOTIFY as u16;
+        xkb::select_events(conn, xkb::ID_USE_CORE_KBD as u16, events, 0, events, 0, 0, None);
+        Self::read_state(conn, &mut state);
+        conn.flush();
+    }
+
+    fn x_event(&self, conn: &xcb::Connection, event: &xcb::GenericEvent) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let r = event.response_type() & !0x80;
+
+        if r == xcb::PROPERTY_NOTIFY {
+            let ev: &xcb::PropertyNotifyEvent = unsafe { xcb::cast_event(event) };
+            if ev.window() != state.root || ev.atom() != state.xkb_rules_names {
+                return false;
+            }
+            Self::read_layouts(conn, &mut state);
+            return true;
+        }
+
+        // all XKB events share one event code, the XKB type tells them apart
+        if state.xkb_event != Some(r) {
+            return false;
+        }
+        let ev: &xkb::StateNotifyEvent = unsafe { xcb::cast_event(event) };
+        if ev.xkb_type() != xkb::STATE_NOTIFY {
+            return false;
+        }
+        // every modifier press is reported, only redraw on what we show
+        let changed = ev.group() != state.group || ev.locked_mods() != state.locked_mods;
+        state.group = ev.group();
+        state.locked_mods = ev.locked_mods();
+        changed
+    }
+
+    fn click(&self, conn: &xcb::Connection, click: &Click) -> bool {
+        let state = self.state.lock().unwrap();
+        let n = state.layouts.len() as u8;
+        if n < 2 {
+            return false;
+        }
+        let group = match click.button {
+            BUTTON_LEFT | SCROLL_DOWN => (state.group + 1) % n,
+            BUTTON_RIGHT | SCROLL_UP => (state.group + n - 1) % n,
+            _ => return false,
+        };
+        // the server answers with a StateNotify
+        xkb::latch_lock_state(conn, xkb::ID_USE_CORE_KBD as u16, 0, 0, true, group,
+                              0, false, 0);
+        conn.flush();
+        false
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use super::BarModule;
+
+pub struct Load {
+    interval: u64,
+    // placeholders: {load1} {load5} {load15} per core, {load1_raw}
+    // {load5_raw} {load15_raw} as reported, {cores} and {uptime}
+    format: &'static str,
+    // 1 minute load per core for the warning and critical colors
+    warn: f64,
+    critical: f64,
+    state: Arc<Mutex<Option<LoadInfo>>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadInfo {
+    pub load: [f64; 3],
+    pub cores: usize,
+    // seconds
+    pub uptime: Option<u64>,
+}
+
+impl LoadInfo {
+    pub fn per_core(&self, i: usize) -> f64 {
+        self.load[i] / self.cores.max(1) as f64
+    }
+}
+
+// 1, 5 and 15 minute load from the contents of /proc/loadavg
+pub fn parse_loadavg(s: &str) -> Option<[f64; 3]> {
+    let mut f = s.split_whitespace().map(|v| v.parse::<f64>().ok());
+    Some([f.next()??, f.next()??, f.next()??])
+}
+
+// seconds since boot from the contents of /proc/uptime
+pub fn parse_uptime(s: &str) -> Option<u64> {
+    s.split_whitespace()
+        .next()?
+        .parse::<f64>()
+        .ok()
+        .map(|u| u as u64)
+}
+
+impl Load {
+    pub fn new(interval: u64, format: &'static str, warn: f64, critical: f64) -> Load {
+        Load {
+            interval,
+            format,
+            warn,
+            critical,
+            state: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn read() -> Option<LoadInfo> {
+        let load = parse_loadavg(&std::fs::read_to_string("/proc/loadavg").ok()?)?;
+        Some(LoadInfo {
+            load,
+            cores: thread::available_parallelism().map_or(1, |n| n.get()),
+            uptime: std::fs::read_to_string("/proc/uptime")
+                .ok()
+                .and_then(|s| parse_uptime(&s)),
+        })
+    }
+}
+
+impl BarModule for Load {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let info = match *self.state.lock().unwrap() {
+            Some(i) => i,
+            None => return align,
+        };
+        let color_box = if info.per_core(0) >= self.critical {
+            COLOR_BG_LOAD_CRITICAL
+        } else if info.per_core(0) >= self.warn {
+            COLOR_BG_LOAD_WARN
+        } else {
+            COLOR_BG_LOAD
+        };
+
+        let text = format_placeholders(self.format, &[
+            ("load1_raw", format!("{:.2}", info.load[0])),
+            ("load5_raw", format!("{:.2}", info.load[1])),
+            ("load15_raw", format!("{:.2}", info.load[2])),
+            ("load1", format!("{:.2}", info.per_core(0))),
+            ("load5", format!("{:.2}", info.per_core(1))),
+            ("load15", format!("{:.2}", info.per_core(2))),
+            ("cores", info.cores.to_string()),
+            ("uptime", info.uptime.map_or(String::from("-"), compact_duration)),
+        ]);
+        let b = CairoTextBox {
+            text,
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box,
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        let interval = self.interval;
+        thread::spawn(move || {
+            loop {
+                *state.lock().unwrap() = Self::read();
+                signal_bar_redraw(bar_state.clone());
+                thread::sleep(Duration::from_millis(interval));
+            }
+        });
+    }
+}
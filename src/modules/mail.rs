@@ -0,0 +1,272 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use super::{BarModule, Click, BUTTON_LEFT};
+
+// without inotify the folders are counted this often (ms)
+const FALLBACK_POLL: u64 = 60000;
+
+pub struct Mail {
+    // Maildir folders, "~/" is relative to $HOME
+    folders: Vec<PathBuf>,
+    // also count messages in cur/ that don't have the seen flag
+    unseen_cur: bool,
+    // placeholders: {count}
+    format: &'static str,
+    // run on left click, e.g. "urxvt -e neomutt"
+    command: Option<&'static str>,
+    state: Arc<Mutex<usize>>,
+}
+
+// messages in cur/ are named "<unique>:2,<flags>", S marks them seen
+pub fn is_unseen(file_name: &str) -> bool {
+    match file_name.rsplit_once(":2,") {
+        Some((_, flags)) => !flags.contains('S'),
+        None => true,
+    }
+}
+
+fn messages(dir: &Path) -> impl Iterator<Item = String> {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|n| !n.starts_with('.'))
+}
+
+// unread messages in one Maildir folder
+pub fn count_unread(folder: &Path, unseen_cur: bool) -> usize {
+    let new = messages(&folder.join("new")).count();
+    let cur = if unseen_cur {
+        messages(&folder.join("cur")).filter(|n| is_unseen(n)).count()
+    } else {
+        0
+    };
+    new + cur
+}
+
+impl Mail {
+    pub fn new(folders: Vec<&'static str>, unseen_cur: bool, format: &'static str,
+               command: Option<&'static str>) -> Mail {
+        Mail {
+            folders: folders.iter().map(|f| expand_home(f)).collect(),
+            unseen_cur,
+            format,
+            command,
+            state: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    // watches new/ (and cur/) of every folder, again for folders already
+    // watched. Returns false if some don't exist, e.g. before the first
+    // sync created them.
+    fn add_watches(inotify: &File, folders: &[PathBuf], unseen_cur: bool) -> bool {
+        let subdirs: &[&str] = if unseen_cur { &["new", "cur"] } else { &["new"] };
+        let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+        let mut all = true;
+        for f in folders {
+            for d in subdirs {
+                let path = CString::new(f.join(d).as_os_str().as_encoded_bytes())
+                    .unwrap_or_default();
+                let wd = unsafe {
+                    libc::inotify_add_watch(inotify.as_raw_fd(), path.as_ptr(), mask)
+                };
+                all &= wd >= 0;
+            }
+        }
+        all
+    }
+}
+
+// whether one of the inotify events in buf is a removed watch, its
+// directory was deleted or moved away
+fn watch_removed(buf: &[u8]) -> bool {
+    const EVENT_HDRLEN: usize = 16;
+    let mut i = 0;
+    while i + EVENT_HDRLEN <= buf.len() {
+        let field = |o: usize| u32::from_ne_bytes([buf[i + o], buf[i + o + 1],
+                                                   buf[i + o + 2], buf[i + o + 3]]);
+        if field(4) & libc::IN_IGNORED != 0 {
+            return true;
+        }
+        i += EVENT_HDRLEN + field(12) as usize;
+    }
+    false
+}
+
+impl BarModule for Mail {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let count = *self.state.lock().unwrap();
+        let b = CairoTextBox {
+            text: format_placeholders(self.format, &[("count", count.to_string())]),
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box: if count > 0 { COLOR_BG_MAIL_UNREAD } else { COLOR_BG_MAIL },
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn click(&self, _conn: &xcb::Connection, click: &Click) -> bool {
+        if let (BUTTON_LEFT, Some(cmd)) = (click.button, self.command) {
+            spawn_command(cmd);
+        }
+        false
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        let folders = self.folders.clone();
+        let unseen_cur = self.unseen_cur;
+        thread::spawn(move || {
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+            let inotify = if fd >= 0 {
+                Some(unsafe { File::from_raw_fd(fd) })
+            } else {
+                None
+            };
+            let mut watched = inotify.as_ref()
+                .is_some_and(|f| Self::add_watches(f, &folders, unseen_cur));
+            let mut buf = [0u8; 4096];
+            loop {
+                *state.lock().unwrap() = folders.iter()
+                    .map(|f| count_unread(f, unseen_cur))
+                    .sum();
+                signal_bar_redraw(bar_state.clone());
+
+                match inotify.as_ref() {
+                    Some(mut f) => {
+                        let mut pfd = libc::pollfd {
+                            fd: f.as_raw_fd(),
+                            events: libc::POLLIN,
+                            revents: 0,
+                        };
+                        // folders that are missing are counted this often,
+                        // and watched once they're there
+                        if unsafe { libc::poll(&mut pfd, 1, FALLBACK_POLL as i32) } <= 0 {
+                            if !watched {
+                                watched = Self::add_watches(f, &folders, unseen_cur);
+                            }
+                            continue;
+                        }
+                        // deliveries and syncs come in bursts, count once
+                        // they've settled
+                        thread::sleep(Duration::from_millis(100));
+                        while unsafe { libc::poll(&mut pfd, 1, 0) } > 0 {
+                            match f.read(&mut buf) {
+                                Ok(n) if watch_removed(&buf[..n]) => watched = false,
+                                _ => {}
+                            }
+                        }
+                    }
+                    None => thread::sleep(Duration::from_millis(FALLBACK_POLL)),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Maildir folder in the temp directory, removed on drop
+    struct Maildir(PathBuf);
+
+    impl Maildir {
+        fn new(test: &str, new: &[&str], cur: &[&str]) -> Maildir {
+            let root = std::env::temp_dir()
+                .join(format!("ggbar-maildir-{}-{}", std::process::id(), test));
+            let _ = fs::remove_dir_all(&root);
+            for (sub, names) in &[("new", new), ("cur", cur), ("tmp", &[][..])] {
+                fs::create_dir_all(root.join(sub)).unwrap();
+                for n in names.iter() {
+                    fs::write(root.join(sub).join(n), "Subject: test\n\n").unwrap();
+                }
+            }
+            Maildir(root)
+        }
+    }
+
+    impl Drop for Maildir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn unseen() {
+        assert!(is_unseen("1603000000.M1P2.host:2,"));
+        assert!(is_unseen("1603000000.M1P2.host:2,FR"));
+        assert!(!is_unseen("1603000000.M1P2.host:2,S"));
+        assert!(!is_unseen("1603000000.M1P2.host:2,FRS"));
+        // without info, e.g. moved from new/ by hand
+        assert!(is_unseen("1603000000.M1P2.host"));
+        // only flags after the last ":2," count
+        assert!(is_unseen("1603000000.M1P2.S:2,:2,F"));
+    }
+
+    #[test]
+    fn count() {
+        let m = Maildir::new("count", &[
+            "1603000001.M1P2.host",
+            "1603000002.M1P2.host",
+            ".hidden",
+        ], &[
+            "1603000003.M1P2.host:2,",
+            "1603000004.M1P2.host:2,S",
+            "1603000005.M1P2.host:2,RS",
+            "1603000006.M1P2.host:2,F",
+        ]);
+        assert_eq!(count_unread(&m.0, false), 2);
+        assert_eq!(count_unread(&m.0, true), 4);
+    }
+
+    fn read_events(inotify: &mut File) -> Vec<u8> {
+        let mut buf = [0u8; 4096];
+        let n = inotify.read(&mut buf).unwrap();
+        buf[..n].to_vec()
+    }
+
+    #[test]
+    fn watches() {
+        let m = Maildir::new("watches", &[], &[]);
+        let folders = [m.0.clone(), m.0.join("later")];
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        assert!(fd >= 0);
+        let mut inotify = unsafe { File::from_raw_fd(fd) };
+
+        // not synced yet
+        assert!(!Mail::add_watches(&inotify, &folders, true));
+        fs::create_dir_all(m.0.join("later/new")).unwrap();
+        fs::create_dir_all(m.0.join("later/cur")).unwrap();
+        assert!(Mail::add_watches(&inotify, &folders, true));
+
+        fs::write(m.0.join("later/new/1603000001.M1P2.host"), "").unwrap();
+        assert!(!watch_removed(&read_events(&mut inotify)));
+        // the folder is deleted by a resync
+        fs::remove_dir_all(m.0.join("later")).unwrap();
+        assert!(watch_removed(&read_events(&mut inotify)));
+        assert!(!Mail::add_watches(&inotify, &folders, true));
+    }
+
+    #[test]
+    fn count_missing() {
+        let m = Maildir::new("missing", &[], &[]);
+        assert_eq!(count_unread(&m.0, true), 0);
+        assert_eq!(count_unread(&m.0.join("nope"), true), 0);
+    }
+}
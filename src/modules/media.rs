@@ -0,0 +1,575 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use dbus::arg::{PropMap, RefArg};
+use dbus::blocking::Connection;
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::message::{MatchRule, Message};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use super::{BarModule, Click, BUTTON_LEFT, BUTTON_MIDDLE, BUTTON_RIGHT};
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const MPRIS_PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+const DBUS_TIMEOUT: Duration = Duration::from_millis(1000);
+
+pub struct Media {
+    // placeholders: {status} {artist} {title} {track}, where {track} is
+    // "artist - title" or only the title if there is no artist
+    format: &'static str,
+    // address of the bus the players are on, None for the session bus
+    bus: Option<String>,
+    state: Arc<Mutex<Vec<Player>>>,
+    // (bus name, method) for the command thread
+    commands: Sender<(String, &'static str)>,
+    commands_rx: Mutex<Option<Receiver<(String, &'static str)>>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Player {
+    // well-known name, e.g. org.mpris.MediaPlayer2.mpv
+    pub bus_name: String,
+    // unique name, signals are sent from this one
+    pub owner: String,
+    pub status: PlaybackStatus,
+    pub artist: String,
+    pub title: String,
+}
+
+impl PlaybackStatus {
+    fn parse(s: &str) -> PlaybackStatus {
+        match s {
+            "Playing" => PlaybackStatus::Playing,
+            "Paused" => PlaybackStatus::Paused,
+            _ => PlaybackStatus::Stopped,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            PlaybackStatus::Playing => MEDIA_PLAYING_SYMBOL,
+            PlaybackStatus::Paused => MEDIA_PAUSED_SYMBOL,
+            PlaybackStatus::Stopped => MEDIA_STOPPED_SYMBOL,
+        }
+    }
+}
+
+impl Player {
+    // applies PlaybackStatus and Metadata from a GetAll reply or a
+    // PropertiesChanged signal
+    pub fn update(&mut self, props: &PropMap) {
+        if let Some(s) = props.get("PlaybackStatus").and_then(|v| v.as_str()) {
+            self.status = PlaybackStatus::parse(s);
+        }
+        if let Some(m) = props.get("Metadata") {
+            let (artist, title) = parse_metadata(&m.0);
+            self.artist = artist;
+            self.title = title;
+        }
+    }
+
+    fn track(&self) -> String {
+        if self.artist.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} \u{2013} {}", self.artist, self.title)
+        }
+    }
+}
+
+// (artist, title) from the a{sv} metadata dict. xesam:artist should be a
+// list of strings, but some players send a single string.
+pub fn parse_metadata(metadata: &dyn RefArg) -> (String, String) {
+    let mut artist = String::new();
+    let mut title = String::new();
+    let mut entries = match metadata.as_iter() {
+        Some(i) => i,
+        None => return (artist, title),
+    };
+    while let (Some(k), Some(v)) = (entries.next(), entries.next()) {
+        match k.as_str() {
+            Some("xesam:title") => title = String::from(v.as_str().unwrap_or("")),
+            Some("xesam:artist") => {
+                // as_iter() on a variant yields its value, not the list items
+                let value = v.as_iter().and_then(|mut i| i.next());
+                artist = match value.and_then(|a| a.as_iter()) {
+                    Some(list) => list.filter_map(|a| a.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", "),
+                    None => String::from(v.as_str().unwrap_or("")),
+                };
+            }
+            _ => {}
+        }
+    }
+    (artist, title)
+}
+
+// the player to show and control: the first playing one, otherwise the
+// first paused one, otherwise the first one
+pub fn active_player(players: &[Player]) -> Option<&Player> {
+    players.iter()
+        .find(|p| p.status == PlaybackStatus::Playing)
+        .or_else(|| players.iter().find(|p| p.status == PlaybackStatus::Paused))
+        .or_else(|| players.first())
+}
+
+fn query_player(conn: &Connection, bus_name: &str) -> Result<Player, dbus::Error> {
+    let bus = conn.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", DBUS_TIMEOUT);
+    let (owner,): (String,) = bus.method_call("org.freedesktop.DBus", "GetNameOwner",
+                                              (bus_name,))?;
+    let props = conn.with_proxy(bus_name, MPRIS_PATH, DBUS_TIMEOUT).get_all(MPRIS_PLAYER)?;
+    let mut player = Player {
+        bus_name: String::from(bus_name),
+        owner,
+        status: PlaybackStatus::Stopped,
+        artist: String::new(),
+        title: String::new(),
+    };
+    player.update(&props);
+    Ok(player)
+}
+
+fn list_players(conn: &Connection) -> Result<Vec<Player>, dbus::Error> {
+    let bus = conn.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", DBUS_TIMEOUT);
+    let (mut names,): (Vec<String>,) = bus.method_call("org.freedesktop.DBus", "ListNames", ())?;
+    names.sort();
+    Ok(names.iter()
+        .filter(|n| n.starts_with(MPRIS_PREFIX))
+        .filter_map(|n| query_player(conn, n).ok())
+        .collect())
+}
+
+fn connect(bus: Option<&str>) -> Result<Connection, dbus::Error> {
+    match bus {
+        Some(address) => Connection::new_address(address),
+        None => Connection::new_session(),
+    }
+}
+
+// follows players until the bus connection is lost
+fn watch(bus: Option<&str>, state: &Arc<Mutex<Vec<Player>>>,
+         bar_state: &Arc<(Mutex<BarState>, Condvar)>) -> Result<(), dbus::Error> {
+    let conn = connect(bus)?;
+
+    let props_rule = MatchRule::new_signal("org.freedesktop.DBus.Properties",
+                                           "PropertiesChanged")
+        .with_path(MPRIS_PATH);
+    let owner_rule = MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged")
+        .with_sender("org.freedesktop.DBus");
+
+    // players that appeared, they are queried outside the callbacks
+    let appeared: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+
+    let s = state.clone();
+    let bs = bar_state.clone();
+    conn.add_match(props_rule, move |(): (), _, msg: &Message| {
+        let (iface, changed) = match msg.read2::<String, PropMap>() {
+            Ok(a) => a,
+            Err(_) => return true,
+        };
+        let sender = msg.sender().map(|s| s.to_string()).unwrap_or_default();
+        if iface == MPRIS_PLAYER {
+            let mut players = s.lock().unwrap();
+            for p in players.iter_mut().filter(|p| p.owner == sender) {
+                p.update(&changed);
+            }
+            drop(players);
+            signal_bar_redraw(bs.clone());
+        }
+        true
+    })?;
+
+    let s = state.clone();
+    let bs = bar_state.clone();
+    let a = appeared.clone();
+    conn.add_match(owner_rule, move |(name, _old, new): (String, String, String), _, _| {
+        if name.starts_with(MPRIS_PREFIX) {
+            s.lock().unwrap().retain(|p| p.bus_name != name);
+            if new.is_empty() {
+                signal_bar_redraw(bs.clone());
+            } else {
+                a.lock().unwrap().push(name);
+            }
+        }
+        true
+    })?;
+
+    *state.lock().unwrap() = list_players(&conn)?;
+    signal_bar_redraw(bar_state.clone());
+
+    loop {
+        conn.process(Duration::from_secs(60))?;
+
+        let names: Vec<String> = appeared.lock().unwrap().drain(..).collect();
+        if names.is_empty() {
+            continue;
+        }
+        for name in names {
+            if let Ok(p) = query_player(&conn, &name) {
+                let mut players = state.lock().unwrap();
+                players.retain(|old| old.bus_name != name);
+                players.push(p);
+            }
+        }
+        signal_bar_redraw(bar_state.clone());
+    }
+}
+
+impl Media {
+    pub fn new(format: &'static str) -> Media {
+        let (commands, commands_rx) = mpsc::channel();
+        Media {
+            format,
+            bus: None,
+            state: Arc::new(Mutex::new(Vec::new())),
+            commands,
+            commands_rx: Mutex::new(Some(commands_rx)),
+        }
+    }
+
+    // asks the active player to do what the button is for, the player
+    // answers with PropertiesChanged
+    fn command(&self, button: u8) {
+        let method = match button {
+            BUTTON_LEFT => "PlayPause",
+            BUTTON_MIDDLE => "Previous",
+            BUTTON_RIGHT => "Next",
+            _ => return,
+        };
+        if let Some(p) = active_player(&self.state.lock().unwrap()) {
+            let _ = self.commands.send((p.bus_name.clone(), method));
+        }
+    }
+}
+
+impl BarModule for Media {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let players = self.state.lock().unwrap();
+        let player = match active_player(&players) {
+            Some(p) => p,
+            None => return align,
+        };
+
+        let text = format_placeholders(self.format, &[
+            ("status", String::from(player.status.symbol())),
+            ("artist", player.artist.clone()),
+            ("title", player.title.clone()),
+            ("track", player.track()),
+        ]);
+        let b = CairoTextBox {
+            text,
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box: COLOR_BG_MEDIA,
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn click(&self, _conn: &xcb::Connection, click: &Click) -> bool {
+        self.command(click.button);
+        false
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        // method calls block, so clicks are sent from their own thread
+        if let Some(rx) = self.commands_rx.lock().unwrap().take() {
+            let bus = self.bus.clone();
+            thread::spawn(move || {
+                let mut conn = None;
+                for (bus_name, method) in rx {
+                    if conn.is_none() {
+                        conn = connect(bus.as_deref()).ok();
+                    }
+                    let ok = conn.as_ref().is_some_and(|c: &Connection| {
+                        c.with_proxy(bus_name, MPRIS_PATH, DBUS_TIMEOUT)
+                            .method_call::<(), _, _, _>(MPRIS_PLAYER, method, ())
+                            .is_ok()
+                    });
+                    if !ok {
+                        conn = None;
+                    }
+                }
+            });
+        }
+
+        let bus = self.bus.clone();
+        let state = self.state.clone();
+        thread::spawn(move || {
+            loop {
+                let _ = watch(bus.as_deref(), &state, &bar_state);
+                // no session bus yet, or it went away
+                state.lock().unwrap().clear();
+                signal_bar_redraw(bar_state.clone());
+                thread::sleep(Duration::from_secs(5));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::SCROLL_UP;
+    use dbus::arg::Variant;
+    use dbus::channel::{MatchingReceiver, Sender as _};
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::time::Instant;
+
+    type Metadata = PropMap;
+
+    fn var<T: RefArg + 'static>(v: T) -> Variant<Box<dyn RefArg>> {
+        Variant(Box::new(v))
+    }
+
+    // props as they come out of a PropertiesChanged signal
+    fn received(props: PropMap) -> PropMap {
+        let msg = Message::new_signal(MPRIS_PATH, "org.freedesktop.DBus.Properties",
+                                      "PropertiesChanged")
+            .unwrap()
+            .append1(props);
+        msg.read1().unwrap()
+    }
+
+    fn props(status: Option<&str>, metadata: Option<Metadata>) -> PropMap {
+        let mut p = PropMap::new();
+        if let Some(s) = status {
+            p.insert(String::from("PlaybackStatus"), var(String::from(s)));
+        }
+        if let Some(m) = metadata {
+            p.insert(String::from("Metadata"), var(m));
+        }
+        received(p)
+    }
+
+    fn metadata(artist: Option<Variant<Box<dyn RefArg>>>, title: &str) -> Metadata {
+        let mut m = Metadata::new();
+        m.insert(String::from("mpris:trackid"),
+                 var(dbus::Path::from("/org/mpris/MediaPlayer2/Track/1")));
+        m.insert(String::from("mpris:length"), var(215000000i64));
+        m.insert(String::from("xesam:title"), var(String::from(title)));
+        if let Some(a) = artist {
+            m.insert(String::from("xesam:artist"), a);
+        }
+        m
+    }
+
+    fn player(bus_name: &str, status: PlaybackStatus) -> Player {
+        Player {
+            bus_name: String::from(bus_name),
+            owner: String::from(":1.42"),
+            status,
+            artist: String::new(),
+            title: String::new(),
+        }
+    }
+
+    #[test]
+    fn metadata_artist_list() {
+        let artists = vec![String::from("Daft Punk"), String::from("Pharrell Williams")];
+        let p = props(None, Some(metadata(Some(var(artists)), "Get Lucky")));
+        assert_eq!(parse_metadata(&p["Metadata"].0),
+                   (String::from("Daft Punk, Pharrell Williams"), String::from("Get Lucky")));
+    }
+
+    #[test]
+    fn metadata_artist_string() {
+        let p = props(None, Some(metadata(Some(var(String::from("Daft Punk"))), "One More Time")));
+        assert_eq!(parse_metadata(&p["Metadata"].0),
+                   (String::from("Daft Punk"), String::from("One More Time")));
+    }
+
+    #[test]
+    fn metadata_without_artist() {
+        let p = props(None, Some(metadata(None, "stream.mp3")));
+        assert_eq!(parse_metadata(&p["Metadata"].0),
+                   (String::new(), String::from("stream.mp3")));
+        // not a dict at all
+        assert_eq!(parse_metadata(&String::from("x")), (String::new(), String::new()));
+    }
+
+    #[test]
+    fn update() {
+        let mut p = player("org.mpris.MediaPlayer2.mpv", PlaybackStatus::Stopped);
+        let artists = vec![String::from("Boards of Canada")];
+        p.update(&props(Some("Playing"), Some(metadata(Some(var(artists)), "Roygbiv"))));
+        assert_eq!(p.status, PlaybackStatus::Playing);
+        assert_eq!(p.track(), "Boards of Canada \u{2013} Roygbiv");
+
+        // changes only carry what changed
+        p.update(&props(Some("Paused"), None));
+        assert_eq!(p.status, PlaybackStatus::Paused);
+        assert_eq!(p.title, "Roygbiv");
+
+        p.update(&props(None, Some(metadata(None, "stream.mp3"))));
+        assert_eq!(p.track(), "stream.mp3");
+    }
+
+    #[test]
+    fn active() {
+        let a = player("org.mpris.MediaPlayer2.a", PlaybackStatus::Stopped);
+        let b = player("org.mpris.MediaPlayer2.b", PlaybackStatus::Paused);
+        let c = player("org.mpris.MediaPlayer2.c", PlaybackStatus::Playing);
+        let d = player("org.mpris.MediaPlayer2.d", PlaybackStatus::Paused);
+        let name = |players: &[Player]| active_player(players).map(|p| p.bus_name.clone());
+
+        assert_eq!(name(&[a.clone(), b.clone(), c.clone(), d.clone()]), Some(c.bus_name.clone()));
+        // first paused one without a playing one
+        assert_eq!(name(&[a.clone(), d.clone(), b.clone()]), Some(d.bus_name.clone()));
+        assert_eq!(name(std::slice::from_ref(&a)), Some(a.bus_name.clone()));
+        assert_eq!(name(&[]), None);
+    }
+
+    // a bus of our own, killed on drop
+    struct DbusDaemon {
+        child: Child,
+        address: String,
+    }
+
+    impl DbusDaemon {
+        // None if dbus-daemon isn't installed
+        fn start() -> Option<DbusDaemon> {
+            let mut child = Command::new("dbus-daemon")
+                .args(["--session", "--print-address", "--nofork"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(child.stdout.take()?).read_line(&mut address).ok()?;
+            Some(DbusDaemon {
+                child,
+                address: String::from(address.trim_end()),
+            })
+        }
+    }
+
+    impl Drop for DbusDaemon {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    // org.mpris.MediaPlayer2.fake on the bus, sends the props it gets from
+    // `changes` as PropertiesChanged and the player methods called on it to
+    // `calls`
+    fn fake_player(address: &str, initial: PropMap, changes: Receiver<PropMap>,
+                   calls: Sender<String>) {
+        let conn = Connection::new_address(address).unwrap();
+        conn.request_name("org.mpris.MediaPlayer2.fake", false, true, true).unwrap();
+        thread::spawn(move || {
+            let initial = Mutex::new(Some(initial));
+            conn.start_receive(MatchRule::new_method_call(), Box::new(move |msg, conn| {
+                let reply = match (msg.interface().as_deref(), msg.member().as_deref()) {
+                    (Some("org.freedesktop.DBus.Properties"), Some("GetAll")) => {
+                        let props = initial.lock().unwrap().take().unwrap_or_default();
+                        msg.method_return().append1(props)
+                    }
+                    (Some(MPRIS_PLAYER), Some(method)) => {
+                        let _ = calls.send(String::from(method));
+                        msg.method_return()
+                    }
+                    _ => msg.error(&"org.freedesktop.DBus.Error.UnknownMethod".into(),
+                                   &std::ffi::CString::new("unknown method").unwrap()),
+                };
+                let _ = conn.send(reply);
+                true
+            }));
+            // until the bus is gone at the end of the test
+            while conn.process(Duration::from_millis(10)).is_ok() {
+                for props in changes.try_iter() {
+                    let signal = Message::new_signal(MPRIS_PATH,
+                                                     "org.freedesktop.DBus.Properties",
+                                                     "PropertiesChanged")
+                        .unwrap()
+                        .append3(MPRIS_PLAYER, props, Vec::<String>::new());
+                    let _ = conn.send(signal);
+                }
+            }
+        });
+    }
+
+    // waits for the module's players to be what `f` is looking for
+    fn wait_for(media: &Media, f: impl Fn(&[Player]) -> bool) {
+        let start = Instant::now();
+        while !f(&media.state.lock().unwrap()) {
+            assert!(start.elapsed() < Duration::from_secs(5), "{:?}", media.state);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn mpris_service() {
+        let daemon = match DbusDaemon::start() {
+            Some(d) => d,
+            None => {
+                eprintln!("dbus-daemon not found, skipping");
+                return;
+            }
+        };
+
+        let (changes, changes_rx) = mpsc::channel();
+        let (calls, calls_rx) = mpsc::channel();
+        let artists = vec![String::from("Stereolab")];
+        fake_player(&daemon.address,
+                    props(Some("Paused"), Some(metadata(Some(var(artists)), "Cybele's Reverie"))),
+                    changes_rx, calls);
+
+        let mut media = Media::new("{status} {track}");
+        media.bus = Some(daemon.address.clone());
+        let bar_state = Arc::new((Mutex::new(BarState {
+            redraw_signaled: false,
+            bar_closed: false,
+            bars: Vec::new(),
+            modules_left: Vec::new(),
+            modules_right: Vec::new(),
+            modules_global: Vec::new(),
+        }), Condvar::new()));
+        media.event_generator(bar_state.clone());
+
+        // the player is found with what GetAll returned
+        wait_for(&media, |p| p.len() == 1);
+        {
+            let players = media.state.lock().unwrap();
+            assert_eq!(players[0].bus_name, "org.mpris.MediaPlayer2.fake");
+            assert_eq!(players[0].status, PlaybackStatus::Paused);
+            assert_eq!(players[0].track(), "Stereolab \u{2013} Cybele's Reverie");
+        }
+        assert!(bar_state.0.lock().unwrap().redraw_signaled);
+
+        // PropertiesChanged updates it
+        bar_state.0.lock().unwrap().redraw_signaled = false;
+        changes.send(props(Some("Playing"), Some(metadata(None, "radio stream")))).unwrap();
+        wait_for(&media, |p| p[0].status == PlaybackStatus::Playing);
+        assert_eq!(media.state.lock().unwrap()[0].track(), "radio stream");
+        assert!(bar_state.0.lock().unwrap().redraw_signaled);
+
+        // clicks call the player's methods
+        let timeout = Duration::from_secs(5);
+        media.command(BUTTON_LEFT);
+        assert_eq!(calls_rx.recv_timeout(timeout).unwrap(), "PlayPause");
+        media.command(BUTTON_RIGHT);
+        assert_eq!(calls_rx.recv_timeout(timeout).unwrap(), "Next");
+        media.command(BUTTON_MIDDLE);
+        assert_eq!(calls_rx.recv_timeout(timeout).unwrap(), "Previous");
+        // scrolling does nothing
+        media.command(SCROLL_UP);
+        assert!(calls_rx.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use crate::utils;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use super::BarModule;
+
+pub struct Memory {
+    interval: u64,
+    // placeholders: {used} {total} {available} {percent}
+    // {swap_used} {swap_total} {swap_percent}
+    format: &'static str,
+    // memory usage in percent at which the block turns COLOR_BG_MEMORY_WARN
+    warn_percent: f64,
+    bar: bool,
+    // count the ZFS ARC as available memory
+    zfs_arc: bool,
+    state: Arc<Mutex<Option<MemInfo>>>,
+}
+
+// all values in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemInfo {
+    pub total: u64,
+    pub available: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+impl MemInfo {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+
+    pub fn swap_used(&self) -> u64 {
+        self.swap_total.saturating_sub(self.swap_free)
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    100.0 * part as f64 / total as f64
+}
+
+// contents of /proc/meminfo, None if MemTotal is missing. Kernels before 3.14
+// have no MemAvailable, it's estimated from free memory and caches instead.
+pub fn parse_meminfo(s: &str) -> Option<MemInfo> {
+    let field = |name: &str| {
+        s.lines()
+            .find(|l| l.split(':').next() == Some(name))
+            .and_then(|l| l.split_whitespace().nth(1))
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(|kb| kb * 1024)
+    };
+
+    let total = field("MemTotal")?;
+    let available = match field("MemAvailable") {
+        Some(a) => a,
+        None => {
+            field("MemFree").unwrap_or(0)
+                + field("Buffers").unwrap_or(0)
+                + field("Cached").unwrap_or(0)
+                + field("SReclaimable").unwrap_or(0)
+        }
+    };
+    Some(MemInfo {
+        total,
+        available: available.min(total),
+        swap_total: field("SwapTotal").unwrap_or(0),
+        swap_free: field("SwapFree").unwrap_or(0),
+    })
+}
+
+// current ARC size in bytes from /proc/spl/kstat/zfs/arcstats
+pub fn parse_arc_size(s: &str) -> Option<u64> {
+    s.lines()
+        .map(|l| l.split_whitespace().collect::<Vec<&str>>())
+        .find(|f| f.len() == 3 && f[0] == "size")
+        .and_then(|f| f[2].parse::<u64>().ok())
+}
+
+impl Memory {
+    pub fn new(interval: u64, format: &'static str, warn_percent: f64,
+               bar: bool, zfs_arc: bool) -> Memory {
+        Memory {
+            interval,
+            format,
+            warn_percent,
+            bar,
+            zfs_arc,
+            state: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn read(zfs_arc: bool) -> Option<MemInfo> {
+        let mut info = parse_meminfo(&std::fs::read_to_string("/proc/meminfo").ok()?)?;
+        if zfs_arc {
+            let arc = std::fs::read_to_string("/proc/spl/kstat/zfs/arcstats")
+                .ok()
+                .and_then(|s| parse_arc_size(&s))
+                .unwrap_or(0);
+            info.available = (info.available + arc).min(info.total);
+        }
+        Some(info)
+    }
+
+    fn text(&self, info: &MemInfo) -> String {
+        format_placeholders(self.format, &[
+            ("used", human_bytes(info.used())),
+            ("total", human_bytes(info.total)),
+            ("available", human_bytes(info.available)),
+            ("percent", format!("{:.0}", percent(info.used(), info.total))),
+            ("swap_used", human_bytes(info.swap_used())),
+            ("swap_total", human_bytes(info.swap_total)),
+            ("swap_percent", format!("{:.0}", percent(info.swap_used(), info.swap_total))),
+        ])
+    }
+}
+
+impl BarModule for Memory {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let info = match *self.state.lock().unwrap() {
+            Some(i) => i,
+            None => return align,
+        };
+        let used = percent(info.used(), info.total);
+        let color_bg = if used >= self.warn_percent {
+            COLOR_BG_MEMORY_WARN
+        } else {
+            COLOR_BG_MEMORY
+        };
+
+        let b = CairoTextBox {
+            text: self.text(&info),
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box: color_bg,
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        if !self.bar {
+            return b.draw(cairo, dyn_config.font.clone());
+        }
+
+        // horizontal bar filling up with used memory, after the text
+        let (left, _, room_left) = b.draw_with_room(cairo, dyn_config.font.clone(),
+                                                    0.0, MEMORY_BAR_WIDTH + BLOCK_MARGIN);
+        let bar_h = MEMORY_BAR_HEIGHT * dyn_config.height;
+        let bar_left = room_left + BLOCK_MARGIN;
+        let bar_top = 0.5 * (dyn_config.height - bar_h);
+        utils::cairo_source_rgb_hex(cairo, 0xff000000);
+        cairo.rectangle(bar_left, bar_top, MEMORY_BAR_WIDTH, bar_h);
+        cairo.fill();
+        let p = used / 100.0;
+        utils::cairo_source_rgb_rgfade(cairo, 1.0 - p);
+        cairo.rectangle(bar_left, bar_top, MEMORY_BAR_WIDTH * p, bar_h);
+        cairo.fill();
+        left
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        let interval = self.interval;
+        let zfs_arc = self.zfs_arc;
+        thread::spawn(move || {
+            loop {
+                *state.lock().unwrap() = Self::read(zfs_arc);
+                signal_bar_redraw(bar_state.clone());
+                thread::sleep(Duration::from_millis(interval));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMINFO: &str = "\
+MemTotal:       16303428 kB
+MemFree:         1234567 kB
+MemAvailable:    8151714 kB
+Buffers:          345678 kB
+Cached:          5678901 kB
+SwapCached:         1024 kB
+Active:          7654321 kB
+Inactive:        4567890 kB
+SReclaimable:     456789 kB
+SwapTotal:       8388604 kB
+SwapFree:        8126460 kB
+HugePages_Total:       0
+Hugepagesize:       2048 kB
+";
+
+    // 3.13 and older have no MemAvailable
+    const MEMINFO_OLD: &str = "\
+MemTotal:        4046348 kB
+MemFree:          512000 kB
+Buffers:          128000 kB
+Cached:          1024000 kB
+SwapCached:            0 kB
+Active:          2048000 kB
+SwapTotal:             0 kB
+SwapFree:              0 kB
+";
+
+    const ARCSTATS: &str = "\
+13 1 0x01 123 33456 9876543210 1234567890123
+name                            type data
+hits                            4    123456789
+misses                          4    1234567
+c_max                           4    8346931200
+size                            4    2147483648
+compressed_size                 4    1073741824
+";
+
+    #[test]
+    fn meminfo() {
+        let m = parse_meminfo(MEMINFO).unwrap();
+        assert_eq!(m, MemInfo {
+            total: 16303428 * 1024,
+            available: 8151714 * 1024,
+            swap_total: 8388604 * 1024,
+            swap_free: 8126460 * 1024,
+        });
+        assert_eq!(m.used(), (16303428 - 8151714) * 1024);
+        assert_eq!(m.swap_used(), (8388604 - 8126460) * 1024);
+    }
+
+    #[test]
+    fn meminfo_before_memavailable() {
+        let m = parse_meminfo(MEMINFO_OLD).unwrap();
+        assert_eq!(m.total, 4046348 * 1024);
+        // free, buffers and cached count as available
+        assert_eq!(m.available, (512000 + 128000 + 1024000) * 1024);
+        assert_eq!(m.used(), (4046348 - 512000 - 128000 - 1024000) * 1024);
+        assert_eq!(m.swap_used(), 0);
+    }
+
+    #[test]
+    fn meminfo_malformed() {
+        assert_eq!(parse_meminfo(""), None);
+        assert_eq!(parse_meminfo("MemFree: 100 kB\n"), None);
+        // available never exceeds the total
+        let m = parse_meminfo("MemTotal: 100 kB\nMemAvailable: 200 kB\n").unwrap();
+        assert_eq!(m.used(), 0);
+    }
+
+    #[test]
+    fn arc_size() {
+        assert_eq!(parse_arc_size(ARCSTATS), Some(2147483648));
+        assert_eq!(parse_arc_size("name type data\nhits 4 1\n"), None);
+        assert_eq!(parse_arc_size("size 4 lots\n"), None);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use crate::netlink::NetlinkSocket;
+use std::ffi::CStr;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::BarModule;
+
+// rtnetlink multicast groups, see <linux/rtnetlink.h>
+const RTMGRP_LINK: u32        = 0x1;
+const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+const RTMGRP_IPV4_ROUTE: u32  = 0x40;
+const RTMGRP_IPV6_IFADDR: u32 = 0x100;
+
+pub struct Network {
+    // None to follow the interface of the default route
+    interface: Option<&'static str>,
+    interval: u64,
+    // placeholders: {iface} {ipv4} {ipv6} {rx} {tx}
+    format: &'static str,
+    state: Arc<Mutex<NetState>>,
+}
+
+#[derive(Default)]
+struct NetState {
+    iface: Option<String>,
+    up: bool,
+    ipv4: Option<Ipv4Addr>,
+    ipv6: Option<Ipv6Addr>,
+    // bytes per second
+    rx_rate: f64,
+    tx_rate: f64,
+    // time and rx/tx byte counters of the last sample
+    counters: Option<(Instant, u64, u64)>,
+}
+
+// interface of the IPv4 default route with the lowest metric, from the
+// contents of /proc/net/route
+pub fn default_route_interface(route: &str) -> Option<String> {
+    route.lines()
+        .skip(1)
+        .filter_map(|l| {
+            let f: Vec<&str> = l.split_whitespace().collect();
+            if f.len() < 8 {
+                return None;
+            }
+            let flags = u32::from_str_radix(f[3], 16).ok()?;
+            let metric = f[6].parse::<u32>().ok()?;
+            // RTF_UP, destination and mask 0.0.0.0
+            if flags & 0x1 == 0 || f[1] != "00000000" || f[7] != "00000000" {
+                return None;
+            }
+            Some((metric, f[0]))
+        })
+        .min()
+        .map(|(_, iface)| String::from(iface))
+}
+
+fn read_sys(iface: &str, file: &str) -> Option<String> {
+    std::fs::read_to_string(format!("/sys/class/net/{}/{}", iface, file))
+        .ok()
+        .map(|s| String::from(s.trim()))
+}
+
+fn is_link_local(a: &Ipv6Addr) -> bool {
+    a.segments()[0] & 0xffc0 == 0xfe80
+}
+
+// first IPv4 and IPv6 address of the interface, global IPv6 addresses are
+// preferred over link local ones
+fn addresses(iface: &str) -> (Option<Ipv4Addr>, Option<Ipv6Addr>) {
+    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifap) } != 0 {
+        return (None, None);
+    }
+
+    let mut v4 = None;
+    let mut v6: Option<Ipv6Addr> = None;
+    let mut cur = ifap;
+    while !cur.is_null() {
+        let ifa = unsafe { &*cur };
+        cur = ifa.ifa_next;
+        if ifa.ifa_addr.is_null() {
+            continue;
+        }
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) };
+        if name.to_bytes() != iface.as_bytes() {
+            continue;
+        }
+        match unsafe { (*ifa.ifa_addr).sa_family } as i32 {
+            libc::AF_INET => {
+                let sa = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                v4.get_or_insert(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr)));
+            }
+            libc::AF_INET6 => {
+                let sa = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                let a = Ipv6Addr::from(sa.sin6_addr.s6_addr);
+                if v6.is_none_or(|old| is_link_local(&old) && !is_link_local(&a)) {
+                    v6 = Some(a);
+                }
+            }
+            _ => {}
+        }
+    }
+    unsafe { libc::freeifaddrs(ifap) };
+    (v4, v6)
+}
+
+fn rate(bytes: u64, prev: u64, secs: f64) -> f64 {
+    if secs <= 0.0 {
+        return 0.0;
+    }
+    bytes.saturating_sub(prev) as f64 / secs
+}
+
+impl Network {
+    pub fn new(interface: Option<&'static str>, interval: u64, format: &'static str) -> Network {
+        Network {
+            interface,
+            interval,
+            format,
+            state: Arc::new(Mutex::new(Default::default())),
+        }
+    }
+
+    // interface, link state and addresses
+    fn refresh_link(state: &Mutex<NetState>, interface: Option<&str>) {
+        let iface = match interface {
+            Some(i) => Some(String::from(i)),
+            None => std::fs::read_to_string("/proc/net/route")
+                .ok()
+                .and_then(|r| default_route_interface(&r)),
+        };
+        let up = iface.as_ref()
+            .and_then(|i| read_sys(i, "operstate"))
+            .is_some_and(|s| s == "up" || s == "unknown");
+        let (ipv4, ipv6) = match &iface {
+            Some(i) => addresses(i),
+            None => (None, None),
+        };
+
+        let mut s = state.lock().unwrap();
+        if s.iface != iface {
+            s.counters = None;
+            s.rx_rate = 0.0;
+            s.tx_rate = 0.0;
+        }
+        s.iface = iface;
+        s.up = up;
+        s.ipv4 = ipv4;
+        s.ipv6 = ipv6;
+    }
+
+    fn sample_rates(state: &Mutex<NetState>) {
+        let iface = match state.lock().unwrap().iface.clone() {
+            Some(i) => i,
+            None => return,
+        };
+        let counter = |f| {
+            read_sys(&iface, &format!("statistics/{}", f))
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        let (rx, tx) = (counter("rx_bytes"), counter("tx_bytes"));
+        let now = Instant::now();
+
+        let mut s = state.lock().unwrap();
+        if let Some((t, prev_rx, prev_tx)) = s.counters {
+            let secs = now.duration_since(t).as_secs_f64();
+            s.rx_rate = rate(rx, prev_rx, secs);
+            s.tx_rate = rate(tx, prev_tx, secs);
+        }
+        s.counters = Some((now, rx, tx));
+    }
+}
+
+impl BarModule for Network {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let s = self.state.lock().unwrap();
+        let (text, color_box) = match &s.iface {
+            Some(iface) if s.up => {
+                let text = format_placeholders(self.format, &[
+                    ("iface", iface.clone()),
+                    ("ipv4", s.ipv4.map_or(String::from("-"), |a| a.to_string())),
+                    ("ipv6", s.ipv6.map_or(String::from("-"), |a| a.to_string())),
+                    ("rx", format!("{}/s", human_bytes(s.rx_rate as u64))),
+                    ("tx", format!("{}/s", human_bytes(s.tx_rate as u64))),
+                ]);
+                (text, COLOR_BG_NETWORK)
+            }
+            Some(iface) => (format!("{} down", iface), COLOR_BG_NETWORK_DOWN),
+            None => (String::from("no network"), COLOR_BG_NETWORK_DOWN),
+        };
+
+        let b = CairoTextBox {
+            text,
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box,
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let interface = self.interface;
+        Self::refresh_link(&self.state, interface);
+
+        // link, address and route changes are pushed by the kernel
+        let groups = RTMGRP_LINK | RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR | RTMGRP_IPV4_ROUTE;
+        let sock = NetlinkSocket::open(libc::NETLINK_ROUTE, groups);
+        let poll_link = Arc::new(AtomicBool::new(sock.is_err()));
+        if let Ok(sock) = sock {
+            let state = self.state.clone();
+            let bar_state = bar_state.clone();
+            let poll_link = poll_link.clone();
+            thread::spawn(move || {
+                let mut buf = vec![0u8; 16384];
+                loop {
+                    match sock.recv(&mut buf) {
+                        Ok(_) => {}
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        // the socket buffer overflowed and events were lost,
+                        // the refresh reads everything again anyway
+                        Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {}
+                        Err(_) => {
+                            poll_link.store(true, Ordering::Relaxed);
+                            return;
+                        }
+                    }
+                    Self::refresh_link(&state, interface);
+                    signal_bar_redraw(bar_state.clone());
+                }
+            });
+        }
+
+        let state = self.state.clone();
+        let interval = self.interval;
+        thread::spawn(move || {
+            loop {
+                if poll_link.load(Ordering::Relaxed) {
+                    Self::refresh_link(&state, interface);
+                }
+                Self::sample_rates(&state);
+                signal_bar_redraw(bar_state.clone());
+                thread::sleep(Duration::from_millis(interval));
+            }
+        });
+    }
+}
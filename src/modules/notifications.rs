@@ -0,0 +1,497 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::decoration;
+use crate::config::*;
+use crate::utils::*;
+use dbus::arg::{PropMap, RefArg};
+use dbus::blocking::SyncConnection;
+use dbus::channel::{MatchingReceiver, Sender};
+use dbus::message::{MatchRule, Message};
+use std::cell::Cell;
+use std::ffi::CString;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::{BarModule, Click, BUTTON_LEFT, BUTTON_MIDDLE, BUTTON_RIGHT};
+
+const NOTIFICATIONS_NAME: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+// gap between the end of a scrolling summary and its next repetition
+const SCROLL_GAP: f64 = 40.0;
+
+// why a notification was closed, see the notification spec
+const CLOSED_EXPIRED: u32   = 1;
+const CLOSED_DISMISSED: u32 = 2;
+const CLOSED_BY_CALL: u32   = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notification {
+    pub id: u32,
+    pub app_name: String,
+    pub summary: String,
+    pub body: String,
+    // (key, label) pairs
+    pub actions: Vec<(String, String)>,
+    pub urgency: Urgency,
+    pub expires: Option<Instant>,
+}
+
+#[derive(Default)]
+struct NotifyState {
+    // in arrival order
+    queue: Vec<Notification>,
+    last_id: u32,
+    // do not disturb, only critical notifications are shown
+    dnd: bool,
+    // whether we own the bus name, we wait in line while another
+    // notification daemon has it
+    owner: bool,
+    conn: Option<Arc<SyncConnection>>,
+}
+
+pub struct Notifications {
+    // placeholders: {app} {summary} {body}
+    format: &'static str,
+    // width of the area the text scrolls in
+    width: f64,
+    state: Arc<Mutex<NotifyState>>,
+    // (id, time) of the notification shown during the last render, scrolling
+    // starts when it's first shown
+    shown: Cell<Option<(u32, Instant)>>,
+    // id of the notification whose text doesn't fit. The text is measured
+    // in logical pixels, it's the same on every bar.
+    overflowing: Cell<Option<u32>>,
+}
+
+impl Urgency {
+    fn from_hints(hints: &PropMap) -> Urgency {
+        match hints.get("urgency").and_then(|u| u.as_u64()) {
+            Some(0) => Urgency::Low,
+            Some(2) => Urgency::Critical,
+            _ => Urgency::Normal,
+        }
+    }
+
+    fn color(&self) -> u32 {
+        match self {
+            Urgency::Low => COLOR_BG_NOTIFICATION_LOW,
+            Urgency::Normal => COLOR_BG_NOTIFICATION,
+            Urgency::Critical => COLOR_BG_NOTIFICATION_CRITICAL,
+        }
+    }
+}
+
+// the flat [key, label, key, label, ...] list from Notify
+pub fn parse_actions(actions: &[String]) -> Vec<(String, String)> {
+    actions.chunks_exact(2)
+        .map(|a| (a[0].clone(), a[1].clone()))
+        .collect()
+}
+
+// expire_timeout is in ms, -1 for the server default and 0 for never.
+// Critical notifications only expire when the sender asks for it.
+pub fn expiry(expire_timeout: i32, urgency: Urgency) -> Option<Duration> {
+    match expire_timeout {
+        0 => None,
+        t if t > 0 => Some(Duration::from_millis(t as u64)),
+        _ if urgency == Urgency::Critical => None,
+        _ => Some(Duration::from_millis(NOTIFICATION_TIMEOUT)),
+    }
+}
+
+// bodies may contain a small subset of HTML, we only draw plain text
+pub fn strip_markup(s: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            '\n' if !in_tag => out.push(' '),
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// the notifications that can be shown, while dnd is set only critical ones
+fn showable(queue: &[Notification], dnd: bool) -> impl Iterator<Item = &Notification> {
+    queue.iter().filter(move |n| !dnd || n.urgency == Urgency::Critical)
+}
+
+// the notification to show: the most urgent one, the oldest of those first
+pub fn current(queue: &[Notification], dnd: bool) -> Option<&Notification> {
+    showable(queue, dnd)
+        .fold(None, |best: Option<&Notification>, n| match best {
+            Some(b) if b.urgency >= n.urgency => Some(b),
+            _ => Some(n),
+        })
+}
+
+fn send_closed(conn: &SyncConnection, id: u32, reason: u32) {
+    let msg = Message::signal(&NOTIFICATIONS_PATH.into(),
+                              &NOTIFICATIONS_NAME.into(),
+                              &"NotificationClosed".into())
+        .append2(id, reason);
+    let _ = conn.send(msg);
+}
+
+fn send_action(conn: &SyncConnection, id: u32, key: &str) {
+    let msg = Message::signal(&NOTIFICATIONS_PATH.into(),
+                              &NOTIFICATIONS_NAME.into(),
+                              &"ActionInvoked".into())
+        .append2(id, key);
+    let _ = conn.send(msg);
+}
+
+// `name` is one of the standard org.freedesktop.DBus.Error names
+fn error_reply(msg: &Message, name: &str, text: &str) -> Message {
+    msg.error(&format!("org.freedesktop.DBus.Error.{}", name).into(),
+              &CString::new(text).unwrap_or_default())
+}
+
+type NotifyArgs = (String, u32, String, String, String, Vec<String>, PropMap, i32);
+
+fn handle_call(state: &Mutex<NotifyState>, conn: &SyncConnection, msg: &Message) -> Message {
+    let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+    match member.as_str() {
+        "GetCapabilities" => msg.method_return().append1(vec!["actions", "body"]),
+        "GetServerInformation" => msg.method_return()
+            .append2("ggbar", "ggbar")
+            .append2(env!("CARGO_PKG_VERSION"), "1.2"),
+        "Notify" => {
+            let (app_name, replaces_id, _icon, summary, body, actions, hints, timeout) =
+                match msg.read_all::<NotifyArgs>() {
+                    Ok(a) => a,
+                    Err(e) => return error_reply(msg, "InvalidArgs", &e.to_string()),
+                };
+            let urgency = Urgency::from_hints(&hints);
+            let mut s = state.lock().unwrap();
+            let id = if replaces_id != 0 && s.queue.iter().any(|n| n.id == replaces_id) {
+                replaces_id
+            } else {
+                s.last_id = s.last_id.wrapping_add(1).max(1);
+                s.last_id
+            };
+            let n = Notification {
+                id,
+                app_name,
+                summary: strip_markup(&summary),
+                body: strip_markup(&body),
+                actions: parse_actions(&actions),
+                urgency,
+                expires: expiry(timeout, urgency).map(|d| Instant::now() + d),
+            };
+            match s.queue.iter_mut().find(|old| old.id == id) {
+                Some(old) => *old = n,
+                None => s.queue.push(n),
+            }
+            msg.method_return().append1(id)
+        }
+        "CloseNotification" => {
+            let id: u32 = msg.read1().unwrap_or(0);
+            let mut s = state.lock().unwrap();
+            if s.queue.iter().any(|n| n.id == id) {
+                s.queue.retain(|n| n.id != id);
+                send_closed(conn, id, CLOSED_BY_CALL);
+            }
+            msg.method_return()
+        }
+        _ => error_reply(msg, "UnknownMethod", "unknown method"),
+    }
+}
+
+// drops expired notifications, returns whether any expired and the time
+// until the next one expires
+fn expire(state: &Mutex<NotifyState>, conn: &SyncConnection) -> (bool, Option<Duration>) {
+    let now = Instant::now();
+    let mut s = state.lock().unwrap();
+    let expired: Vec<u32> = s.queue.iter()
+        .filter(|n| n.expires.is_some_and(|e| e <= now))
+        .map(|n| n.id)
+        .collect();
+    s.queue.retain(|n| !expired.contains(&n.id));
+    for id in expired.iter() {
+        send_closed(conn, *id, CLOSED_EXPIRED);
+    }
+    let next = s.queue.iter()
+        .filter_map(|n| n.expires)
+        .min()
+        .map(|e| e.saturating_duration_since(now));
+    (!expired.is_empty(), next)
+}
+
+// serves org.freedesktop.Notifications until the connection is lost. If
+// another notification daemon owns the name, we get it once that one quits.
+fn serve(state: &Arc<Mutex<NotifyState>>,
+         bar_state: &Arc<(Mutex<BarState>, Condvar)>) -> Result<(), dbus::Error> {
+    let conn = Arc::new(SyncConnection::new_session()?);
+
+    // the bus tells us when the name is ours, it sends these without a match
+    for (member, owner) in [("NameAcquired", true), ("NameLost", false)] {
+        let s = state.clone();
+        let bs = bar_state.clone();
+        let rule = MatchRule::new_signal("org.freedesktop.DBus", member)
+            .with_sender("org.freedesktop.DBus");
+        conn.start_receive(rule, Box::new(move |msg, _| {
+            if msg.read1::<&str>().ok() == Some(NOTIFICATIONS_NAME) {
+                s.lock().unwrap().owner = owner;
+                signal_bar_redraw(bs.clone());
+            }
+            true
+        }));
+    }
+    conn.request_name(NOTIFICATIONS_NAME, false, false, false)?;
+
+    let s = state.clone();
+    let bs = bar_state.clone();
+    let rule = MatchRule::new_method_call()
+        .with_path(NOTIFICATIONS_PATH)
+        .with_interface(NOTIFICATIONS_NAME);
+    conn.start_receive(rule, Box::new(move |msg, conn| {
+        let reply = handle_call(&s, conn, &msg);
+        let _ = conn.send(reply);
+        signal_bar_redraw(bs.clone());
+        true
+    }));
+    state.lock().unwrap().conn = Some(conn.clone());
+
+    loop {
+        let (expired, next) = expire(state, &conn);
+        if expired {
+            signal_bar_redraw(bar_state.clone());
+        }
+        conn.process(next.unwrap_or(Duration::from_secs(3600)))?;
+    }
+}
+
+impl Notifications {
+    pub fn new(format: &'static str, width: f64) -> Notifications {
+        Notifications {
+            format,
+            width,
+            state: Arc::new(Mutex::new(Default::default())),
+            shown: Cell::new(None),
+            overflowing: Cell::new(None),
+        }
+    }
+
+    // text clipped to the reserved area, scrolling if it doesn't fit
+    fn draw_scrolling(&self, dyn_config: &DynamicConfig, cairo: &cairo::Context,
+                      n: &Notification, text: &str, align: f64) -> f64 {
+        // the text scrolls behind the notch on the right
+        let notched = decoration::notched(true, align);
+        let w_notch = if notched { decoration::overhang(dyn_config.height) } else { 0.0 };
+        let block = decoration::Block {
+            right: true,
+            lead: align,
+            width: self.width + 2.0 * BLOCK_MARGIN + w_notch,
+            height: dyn_config.height,
+            notched,
+        };
+        block.draw(cairo, n.urgency.color());
+        let left = align - block.width;
+
+        let pl = setup_pango_layout(cairo, dyn_config.font.clone());
+        pl.set_text(text);
+        let (w, h) = pl.get_size();
+        let w_text = (w / pango::SCALE) as f64;
+        let h_text = (h / pango::SCALE) as f64;
+
+        let overflow = w_text > self.width;
+        self.overflowing.set(Some(n.id).filter(|_| overflow));
+        let offset = match self.shown.get() {
+            Some((_, since)) if overflow => {
+                let scrolled = since.elapsed().as_secs_f64() * NOTIFICATION_SCROLL_SPEED;
+                scrolled % (w_text + SCROLL_GAP)
+            }
+            _ => 0.0,
+        };
+
+        cairo.save();
+        cairo.rectangle(left + BLOCK_MARGIN, 0.0, self.width, dyn_config.height);
+        cairo.clip();
+        cairo_source_rgb_hex(cairo, COLOR_TEXT);
+        let y = 0.5 * (dyn_config.height - h_text);
+        cairo.move_to(left + BLOCK_MARGIN - offset, y);
+        pangocairo::show_layout(cairo, &pl);
+        if overflow {
+            cairo.move_to(left + BLOCK_MARGIN - offset + w_text + SCROLL_GAP, y);
+            pangocairo::show_layout(cairo, &pl);
+        }
+        cairo.restore();
+        left
+    }
+}
+
+impl BarModule for Notifications {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let s = self.state.lock().unwrap();
+        // another notification daemon has the name, or there is no bus
+        if !s.owner {
+            self.shown.set(None);
+            return align;
+        }
+        let n = match current(&s.queue, s.dnd) {
+            Some(n) => n,
+            None => {
+                self.shown.set(None);
+                let (text, color_box) = if s.dnd {
+                    (NOTIFICATION_DND_SYMBOL, COLOR_BG_NOTIFICATION_DND)
+                } else {
+                    (NOTIFICATION_IDLE_SYMBOL, COLOR_BG_NOTIFICATION_LOW)
+                };
+                let b = CairoTextBox {
+                    text: String::from(text),
+                    height: dyn_config.height,
+                    color_text: COLOR_TEXT,
+                    color_box,
+                    alignment: Alignment::Right,
+                    align,
+                    margin: BLOCK_MARGIN,
+                };
+                return b.draw(cairo, dyn_config.font);
+            }
+        };
+
+        if self.shown.get().is_none_or(|(id, _)| id != n.id) {
+            self.shown.set(Some((n.id, Instant::now())));
+        }
+
+        // count of the other notifications that can be shown, rightmost
+        let mut align = align;
+        let count = showable(&s.queue, s.dnd).count();
+        if count > 1 {
+            let b = CairoTextBox {
+                text: format!("+{}", count - 1),
+                height: dyn_config.height,
+                color_text: COLOR_TEXT,
+                color_box: n.urgency.color(),
+                alignment: Alignment::Right,
+                align,
+                margin: BLOCK_MARGIN / 2.0,
+            };
+            align = b.draw(cairo, dyn_config.font.clone());
+        }
+
+        let text = format_placeholders(self.format, &[
+            ("app", n.app_name.clone()),
+            ("summary", n.summary.clone()),
+            ("body", n.body.clone()),
+        ]);
+        self.draw_scrolling(&dyn_config, cairo, n, &text, align)
+    }
+
+    fn click(&self, _conn: &xcb::Connection, click: &Click) -> bool {
+        let mut s = self.state.lock().unwrap();
+        if click.button == BUTTON_MIDDLE {
+            s.dnd = !s.dnd;
+            return true;
+        }
+
+        let (id, default_action) = match current(&s.queue, s.dnd) {
+            Some(n) => (n.id, n.actions.iter().any(|(k, _)| k == "default")),
+            None => return false,
+        };
+        match click.button {
+            BUTTON_LEFT | BUTTON_RIGHT => {
+                s.queue.retain(|n| n.id != id);
+                // the signals are only sent, processing stays with the
+                // server thread
+                if let Some(conn) = &s.conn {
+                    if click.button == BUTTON_LEFT && default_action {
+                        send_action(conn, id, "default");
+                    }
+                    send_closed(conn, id, CLOSED_DISMISSED);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn animation(&self) -> Option<Duration> {
+        let s = self.state.lock().unwrap();
+        let scrolling = current(&s.queue, s.dnd)
+            .is_some_and(|n| self.overflowing.get() == Some(n.id));
+        if scrolling {
+            Some(Duration::from_millis(ANIMATION_FRAME))
+        } else {
+            None
+        }
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        thread::spawn(move || {
+            // a session bus that went away takes the notifications with it
+            while serve(&state, &bar_state).is_err() {
+                {
+                    let mut s = state.lock().unwrap();
+                    s.owner = false;
+                    s.conn = None;
+                    s.queue.clear();
+                }
+                signal_bar_redraw(bar_state.clone());
+                thread::sleep(Duration::from_secs(5));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(id: u32, urgency: Urgency) -> Notification {
+        Notification {
+            id,
+            app_name: String::from("notify-send"),
+            summary: format!("summary {}", id),
+            body: String::new(),
+            actions: Vec::new(),
+            urgency,
+            expires: None,
+        }
+    }
+
+    #[test]
+    fn most_urgent_first() {
+        let queue = [
+            notification(1, Urgency::Low),
+            notification(2, Urgency::Critical),
+            notification(3, Urgency::Normal),
+            notification(4, Urgency::Critical),
+        ];
+        assert_eq!(current(&queue, false).map(|n| n.id), Some(2));
+        assert_eq!(showable(&queue, false).count(), 4);
+        assert_eq!(current(&[], false), None);
+    }
+
+    #[test]
+    fn do_not_disturb() {
+        let queue = [
+            notification(1, Urgency::Normal),
+            notification(2, Urgency::Critical),
+            notification(3, Urgency::Low),
+        ];
+        assert_eq!(current(&queue, true).map(|n| n.id), Some(2));
+        // the "+N" count leaves out the held back ones
+        assert_eq!(showable(&queue, true).count(), 1);
+        assert_eq!(current(&queue[..1], true), None);
+        assert_eq!(showable(&queue[..1], true).count(), 0);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use dbus::blocking::Connection;
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::message::{MatchRule, Message};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use super::BarModule;
+
+const SYSTEMD_NAME: &str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+const SYSTEMD_MANAGER: &str = "org.freedesktop.systemd1.Manager";
+const SYSTEMD_UNIT: &str = "org.freedesktop.systemd1.Unit";
+const DBUS_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bus {
+    System,
+    User,
+}
+
+pub struct Systemd {
+    units: Vec<(Bus, &'static str)>,
+    // placeholders: {failed} {failed_system} {failed_user}, failed units
+    // of the whole system and user manager, not only the watched ones
+    format: &'static str,
+    state: Arc<Mutex<SystemdState>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActiveState {
+    Active,
+    Inactive,
+    Failed,
+    // activating, deactivating or reloading
+    Changing,
+    // no connection to the manager
+    Unknown,
+}
+
+struct SystemdState {
+    // per bus, None without a connection
+    failed_system: Option<u32>,
+    failed_user: Option<u32>,
+    // same order as Systemd::units
+    active: Vec<ActiveState>,
+}
+
+impl ActiveState {
+    pub fn parse(s: &str) -> ActiveState {
+        match s {
+            "active" => ActiveState::Active,
+            "inactive" => ActiveState::Inactive,
+            "failed" => ActiveState::Failed,
+            "activating" | "deactivating" | "reloading" => ActiveState::Changing,
+            _ => ActiveState::Unknown,
+        }
+    }
+
+    fn color(&self) -> u32 {
+        match self {
+            ActiveState::Active => COLOR_BG_SYSTEMD_ACTIVE,
+            ActiveState::Inactive => COLOR_BG_SYSTEMD_INACTIVE,
+            ActiveState::Failed => COLOR_BG_SYSTEMD_FAILED,
+            ActiveState::Changing => COLOR_BG_SYSTEMD_CHANGING,
+            ActiveState::Unknown => COLOR_BG_SYSTEMD_INACTIVE,
+        }
+    }
+}
+
+// object path of a unit, systemd escapes every byte that isn't
+// alphanumeric as _xx, e.g. "dbus.service" -> ".../unit/dbus_2eservice".
+// A leading digit is escaped as well, path elements can't start with one.
+pub fn unit_path(name: &str) -> String {
+    let mut path = String::from("/org/freedesktop/systemd1/unit/");
+    if name.is_empty() {
+        path.push('_');
+    }
+    for (i, b) in name.bytes().enumerate() {
+        if b.is_ascii_alphabetic() || (b.is_ascii_digit() && i > 0) {
+            path.push(b as char);
+        } else {
+            path.push_str(&format!("_{:02x}", b));
+        }
+    }
+    path
+}
+
+fn active_state(conn: &Connection, path: &str) -> ActiveState {
+    conn.with_proxy(SYSTEMD_NAME, path, DBUS_TIMEOUT)
+        .get::<String>(SYSTEMD_UNIT, "ActiveState")
+        .map_or(ActiveState::Unknown, |s| ActiveState::parse(&s))
+}
+
+fn failed_units(conn: &Connection) -> Result<u32, dbus::Error> {
+    conn.with_proxy(SYSTEMD_NAME, SYSTEMD_PATH, DBUS_TIMEOUT)
+        .get::<u32>(SYSTEMD_MANAGER, "NFailedUnits")
+}
+
+impl SystemdState {
+    fn failed(&self, bus: Bus) -> Option<u32> {
+        match bus {
+            Bus::System => self.failed_system,
+            Bus::User => self.failed_user,
+        }
+    }
+
+    fn set_failed(&mut self, bus: Bus, failed: Option<u32>) {
+        match bus {
+            Bus::System => self.failed_system = failed,
+            Bus::User => self.failed_user = failed,
+        }
+    }
+}
+
+// watches the units of one bus until the connection fails
+fn watch(bus: Bus, units: &[(usize, String)], state: &Arc<Mutex<SystemdState>>,
+         bar_state: &Arc<(Mutex<BarState>, Condvar)>) -> Result<(), dbus::Error> {
+    let conn = match bus {
+        Bus::System => Connection::new_system()?,
+        Bus::User => Connection::new_session()?,
+    };
+
+    // object paths that changed, they are queried outside the callback
+    let changed: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+    let rule = MatchRule::new_signal("org.freedesktop.DBus.Properties", "PropertiesChanged")
+        .with_sender(SYSTEMD_NAME);
+    let c = changed.clone();
+    conn.add_match(rule, move |(): (), _, msg: &Message| {
+        if let Some(path) = msg.path() {
+            c.lock().unwrap().push(path.to_string());
+        }
+        true
+    })?;
+    // the manager only sends signals to subscribed clients
+    conn.with_proxy(SYSTEMD_NAME, SYSTEMD_PATH, DBUS_TIMEOUT)
+        .method_call::<(), _, _, _>(SYSTEMD_MANAGER, "Subscribe", ())?;
+
+    let failed = failed_units(&conn)?;
+    {
+        let mut s = state.lock().unwrap();
+        s.set_failed(bus, Some(failed));
+        for (i, path) in units {
+            s.active[*i] = active_state(&conn, path);
+        }
+    }
+    signal_bar_redraw(bar_state.clone());
+
+    loop {
+        conn.process(Duration::from_secs(60))?;
+
+        let paths: Vec<String> = changed.lock().unwrap().drain(..).collect();
+        if paths.is_empty() {
+            continue;
+        }
+        // any unit changing state may change the failed count, which the
+        // manager doesn't always announce itself
+        let failed = failed_units(&conn)?;
+        let watched: Vec<&(usize, String)> = units.iter()
+            .filter(|(_, p)| paths.contains(p))
+            .collect();
+        let active: Vec<ActiveState> = watched.iter()
+            .map(|(_, p)| active_state(&conn, p))
+            .collect();
+
+        let mut s = state.lock().unwrap();
+        let mut redraw = s.failed(bus) != Some(failed);
+        s.set_failed(bus, Some(failed));
+        for ((i, _), a) in watched.iter().zip(active) {
+            redraw |= s.active[*i] != a;
+            s.active[*i] = a;
+        }
+        drop(s);
+        if redraw {
+            signal_bar_redraw(bar_state.clone());
+        }
+    }
+}
+
+impl Systemd {
+    pub fn new(units: Vec<(Bus, &'static str)>, format: &'static str) -> Systemd {
+        let n = units.len();
+        Systemd {
+            units,
+            format,
+            state: Arc::new(Mutex::new(SystemdState {
+                failed_system: None,
+                failed_user: None,
+                active: vec![ActiveState::Unknown; n],
+            })),
+        }
+    }
+}
+
+impl BarModule for Systemd {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let state = self.state.lock().unwrap();
+
+        // units in configured order, left to right
+        let mut right = align;
+        for ((_, name), active) in self.units.iter().zip(state.active.iter()).rev() {
+            let b = CairoTextBox {
+                text: String::from(name.strip_suffix(".service").unwrap_or(name)),
+                height: dyn_config.height,
+                color_text: COLOR_TEXT,
+                color_box: active.color(),
+                alignment: Alignment::Right,
+                align: right,
+                margin: TAG_MARGIN,
+            };
+            right = b.draw(cairo, dyn_config.font.clone()) - TAG_SPACE;
+        }
+
+        let failed_system = state.failed_system.unwrap_or(0);
+        let failed_user = state.failed_user.unwrap_or(0);
+        let failed = failed_system + failed_user;
+        let b = CairoTextBox {
+            text: format_placeholders(self.format, &[
+                ("failed", failed.to_string()),
+                ("failed_system", failed_system.to_string()),
+                ("failed_user", failed_user.to_string()),
+            ]),
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box: if failed > 0 { COLOR_BG_SYSTEMD_FAILED } else { COLOR_BG_SYSTEMD },
+            alignment: Alignment::Right,
+            align: right,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        for bus in [Bus::System, Bus::User] {
+            // (index into state.active, object path)
+            let units: Vec<(usize, String)> = self.units.iter()
+                .enumerate()
+                .filter(|(_, (b, _))| *b == bus)
+                .map(|(i, (_, name))| (i, unit_path(name)))
+                .collect();
+            let state = self.state.clone();
+            let bar_state = bar_state.clone();
+            thread::spawn(move || {
+                loop {
+                    let _ = watch(bus, &units, &state, &bar_state);
+                    // no bus yet, or it went away
+                    {
+                        let mut s = state.lock().unwrap();
+                        s.set_failed(bus, None);
+                        for (i, _) in units.iter() {
+                            s.active[*i] = ActiveState::Unknown;
+                        }
+                    }
+                    signal_bar_redraw(bar_state.clone());
+                    thread::sleep(Duration::from_secs(5));
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!(unit_path("dbus.service"), "/org/freedesktop/systemd1/unit/dbus_2eservice");
+        assert_eq!(unit_path("systemd-journald.service"),
+                   "/org/freedesktop/systemd1/unit/systemd_2djournald_2eservice");
+        assert_eq!(unit_path("getty@tty1.service"),
+                   "/org/freedesktop/systemd1/unit/getty_40tty1_2eservice");
+        assert_eq!(unit_path("1password.service"),
+                   "/org/freedesktop/systemd1/unit/_31password_2eservice");
+        assert_eq!(unit_path(""), "/org/freedesktop/systemd1/unit/_");
+    }
+}
@@ -0,0 +1,304 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use crate::animation;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use super::BarModule;
+
+const HWMON_ROOT: &str = "/sys/class/hwmon";
+
+pub struct Temperature {
+    // (hwmon name, sensor label) pairs, e.g. ("coretemp", "Package id 0").
+    // Sensors without a *_label file match their channel name, e.g. "fan1".
+    sensors: Vec<(&'static str, &'static str)>,
+    interval: u64,
+    // placeholders: {label} {value}
+    format: &'static str,
+    state: Arc<Mutex<Vec<Reading>>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SensorKind {
+    Temp,
+    Fan,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reading {
+    pub label: String,
+    pub kind: SensorKind,
+    // degrees Celsius or RPM
+    pub value: f64,
+    // critical temperature in degrees Celsius
+    pub crit: Option<f64>,
+}
+
+impl Reading {
+    pub fn is_critical(&self) -> bool {
+        self.crit.is_some_and(|c| self.value >= c)
+    }
+
+    fn value_text(&self) -> String {
+        match self.kind {
+            SensorKind::Temp => format!("{:.0}\u{b0}C", self.value),
+            SensorKind::Fan => format!("{:.0} RPM", self.value),
+        }
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path).ok().map(|s| String::from(s.trim()))
+}
+
+fn read_number(path: &Path) -> Option<f64> {
+    read_trimmed(path)?.parse::<f64>().ok()
+}
+
+// directories of all hwmon devices with the given name. Older drivers keep
+// their attributes in the device/ subdirectory.
+fn chip_dirs(root: &Path, chip: &str) -> Vec<PathBuf> {
+    let mut hwmons: Vec<PathBuf> = match std::fs::read_dir(root) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return Vec::new(),
+    };
+    hwmons.sort();
+    hwmons.into_iter()
+        .flat_map(|d| vec![d.join("device"), d])
+        .filter(|d| read_trimmed(&d.join("name")).is_some_and(|n| n == chip))
+        .collect()
+}
+
+// attribute prefix of the matching sensor, e.g. .../hwmon3/temp1
+pub fn find_sensor(root: &Path, chip: &str, label: &str) -> Option<(PathBuf, SensorKind)> {
+    for dir in chip_dirs(root, chip) {
+        let mut channels: Vec<String> = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter_map(|f| f.strip_suffix("_input").map(String::from))
+                .collect(),
+            Err(_) => continue,
+        };
+        channels.sort();
+        for ch in channels {
+            let kind = if ch.starts_with("temp") {
+                SensorKind::Temp
+            } else if ch.starts_with("fan") {
+                SensorKind::Fan
+            } else {
+                continue;
+            };
+            let ch_label = read_trimmed(&dir.join(format!("{}_label", ch)));
+            if ch_label.as_deref().unwrap_or(&ch) == label {
+                return Some((dir.join(ch), kind));
+            }
+        }
+    }
+    None
+}
+
+// current value of a sensor found with find_sensor()
+pub fn read_sensor(prefix: &Path, kind: SensorKind, label: &str) -> Option<Reading> {
+    let attr = |name: &str| {
+        let mut p = prefix.as_os_str().to_owned();
+        p.push(format!("_{}", name));
+        read_number(Path::new(&p))
+    };
+    // temperatures are in millidegrees
+    let (value, crit) = match kind {
+        SensorKind::Temp => (attr("input")? / 1000.0, attr("crit").map(|c| c / 1000.0)),
+        SensorKind::Fan => (attr("input")?, None),
+    };
+    Some(Reading {
+        label: String::from(label),
+        kind,
+        value,
+        crit,
+    })
+}
+
+pub fn read_sensors(root: &Path, sensors: &[(&str, &str)]) -> Vec<Reading> {
+    sensors.iter()
+        .filter_map(|(chip, label)| {
+            let (prefix, kind) = find_sensor(root, chip, label)?;
+            read_sensor(&prefix, kind, label)
+        })
+        .collect()
+}
+
+impl Temperature {
+    pub fn new(sensors: Vec<(&'static str, &'static str)>, interval: u64,
+               format: &'static str) -> Temperature {
+        Temperature {
+            sensors,
+            interval,
+            format,
+            state: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl BarModule for Temperature {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, mut align: f64) -> f64 {
+        // right aligned, the first sensor ends up rightmost
+        for r in self.state.lock().unwrap().iter() {
+            // blinks like a tag with an urgent window
+            let critical = r.is_critical();
+            let color_box = if !critical {
+                COLOR_BG_TEMPERATURE
+            } else if animation::urgent_blink() {
+                COLOR_URGENT_WINDOW
+            } else {
+                COLOR_URGENT_WINDOW_BLINK
+            };
+
+            let text = format_placeholders(self.format, &[
+                ("label", r.label.clone()),
+                ("value", r.value_text()),
+            ]);
+            let b = CairoTextBox {
+                text,
+                height: dyn_config.height,
+                color_text: if critical { COLOR_TEXT_URGENT_WINDOW } else { COLOR_TEXT },
+                color_box,
+                alignment: Alignment::Right,
+                align,
+                margin: BLOCK_MARGIN,
+            };
+            align = b.draw(cairo, dyn_config.font.clone());
+        }
+        align
+    }
+
+    fn animation(&self) -> Option<Duration> {
+        if self.state.lock().unwrap().iter().any(|r| r.is_critical()) {
+            Some(animation::until_urgent_blink())
+        } else {
+            None
+        }
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        let sensors = self.sensors.clone();
+        let interval = self.interval;
+        thread::spawn(move || {
+            loop {
+                // sensors are looked up every time, hwmon numbering isn't
+                // stable across module reloads
+                *state.lock().unwrap() = read_sensors(Path::new(HWMON_ROOT), &sensors);
+                signal_bar_redraw(bar_state.clone());
+                thread::sleep(Duration::from_millis(interval));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // fake /sys/class/hwmon, removed on drop
+    struct Hwmon(PathBuf);
+
+    impl Hwmon {
+        fn new(test: &str) -> Hwmon {
+            let root = std::env::temp_dir()
+                .join(format!("ggbar-hwmon-{}-{}", std::process::id(), test));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            Hwmon(root)
+        }
+
+        fn file(&self, path: &str, contents: &str) {
+            let p = self.0.join(path);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(p, contents).unwrap();
+        }
+    }
+
+    impl Drop for Hwmon {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn hwmon(test: &str) -> Hwmon {
+        let h = Hwmon::new(test);
+        h.file("hwmon0/name", "acpitz\n");
+        h.file("hwmon0/temp1_input", "27800\n");
+        h.file("hwmon0/temp1_crit", "119000\n");
+        h.file("hwmon1/name", "coretemp\n");
+        h.file("hwmon1/temp1_label", "Package id 0\n");
+        h.file("hwmon1/temp1_input", "100000\n");
+        h.file("hwmon1/temp1_crit", "100000\n");
+        h.file("hwmon1/temp2_label", "Core 0\n");
+        h.file("hwmon1/temp2_input", "45000\n");
+        // older drivers keep the attributes in device/
+        h.file("hwmon2/device/name", "thinkpad\n");
+        h.file("hwmon2/device/fan1_input", "2584\n");
+        h.file("hwmon2/device/pwm1", "255\n");
+        h
+    }
+
+    #[test]
+    fn find() {
+        let h = hwmon("find");
+        assert_eq!(find_sensor(&h.0, "coretemp", "Core 0"),
+                   Some((h.0.join("hwmon1/temp2"), SensorKind::Temp)));
+        // channel name without a label file
+        assert_eq!(find_sensor(&h.0, "acpitz", "temp1"),
+                   Some((h.0.join("hwmon0/temp1"), SensorKind::Temp)));
+        assert_eq!(find_sensor(&h.0, "thinkpad", "fan1"),
+                   Some((h.0.join("hwmon2/device/fan1"), SensorKind::Fan)));
+        // a label file hides the channel name
+        assert_eq!(find_sensor(&h.0, "coretemp", "temp1"), None);
+        assert_eq!(find_sensor(&h.0, "nouveau", "temp1"), None);
+    }
+
+    #[test]
+    fn read() {
+        let h = hwmon("read");
+        let r = read_sensors(&h.0, &[
+            ("coretemp", "Package id 0"),
+            ("coretemp", "Core 0"),
+            ("nouveau", "temp1"),
+            ("thinkpad", "fan1"),
+        ]);
+        assert_eq!(r, vec![
+            Reading {
+                label: String::from("Package id 0"),
+                kind: SensorKind::Temp,
+                value: 100.0,
+                crit: Some(100.0),
+            },
+            Reading {
+                label: String::from("Core 0"),
+                kind: SensorKind::Temp,
+                value: 45.0,
+                crit: None,
+            },
+            Reading {
+                label: String::from("fan1"),
+                kind: SensorKind::Fan,
+                value: 2584.0,
+                crit: None,
+            },
+        ]);
+        assert!(r[0].is_critical());
+        assert!(!r[1].is_critical());
+    }
+
+    #[test]
+    fn read_unreadable() {
+        let h = hwmon("unreadable");
+        h.file("hwmon0/temp1_input", "N/A\n");
+        assert!(read_sensors(&h.0, &[("acpitz", "temp1")]).is_empty());
+        assert!(read_sensors(&h.0.join("missing"), &[("acpitz", "temp1")]).is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use crate::animation;
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use super::{BarModule, Click, BUTTON_LEFT, BUTTON_MIDDLE, BUTTON_RIGHT};
+
+// write end of the pipe the signal handler reports SIGUSR1/2 through
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+pub struct Timer {
+    // phase lengths in seconds
+    work: u64,
+    brk: u64,
+    // placeholders: {phase} {remaining}
+    format: &'static str,
+    state: Arc<Mutex<TimerState>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    Work,
+    Break,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Toggle,
+    Reset,
+    Skip,
+}
+
+// persisted across restarts, times are wall clock so they survive them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimerState {
+    pub phase: Phase,
+    // set while running
+    pub ends_at: Option<SystemTime>,
+    // left of the phase while paused
+    pub remaining: Duration,
+    // the last phase ran out and nobody reacted yet
+    pub urgent: bool,
+}
+
+impl Phase {
+    fn next(&self) -> Phase {
+        match self {
+            Phase::Work => Phase::Break,
+            Phase::Break => Phase::Work,
+        }
+    }
+}
+
+// commands written to the FIFO, one per line
+pub fn parse_command(s: &str) -> Option<Command> {
+    match s.trim() {
+        "toggle" | "start" | "pause" => Some(Command::Toggle),
+        "reset" => Some(Command::Reset),
+        "skip" => Some(Command::Skip),
+        _ => None,
+    }
+}
+
+// e.g. 1500 -> "25:00"
+pub fn format_mmss(secs: u64) -> String {
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl TimerState {
+    fn new(phase: Phase, length: Duration) -> TimerState {
+        TimerState {
+            phase,
+            ends_at: None,
+            remaining: length,
+            urgent: false,
+        }
+    }
+
+    pub fn remaining(&self, now: SystemTime) -> Duration {
+        match self.ends_at {
+            Some(end) => end.duration_since(now).unwrap_or_default(),
+            None => self.remaining,
+        }
+    }
+
+    // "<phase> <ends at unix secs or - > <remaining secs> <urgent>"
+    pub fn serialize(&self) -> String {
+        let phase = match self.phase {
+            Phase::Work => "work",
+            Phase::Break => "break",
+        };
+        let ends_at = self.ends_at.map_or(String::from("-"), |e| unix_secs(e).to_string());
+        format!("{} {} {} {}\n", phase, ends_at, self.remaining.as_secs(), self.urgent)
+    }
+
+    pub fn deserialize(s: &str) -> Option<TimerState> {
+        let f: Vec<&str> = s.split_whitespace().collect();
+        if f.len() != 4 {
+            return None;
+        }
+        let phase = match f[0] {
+            "work" => Phase::Work,
+            "break" => Phase::Break,
+            _ => return None,
+        };
+        let ends_at = match f[1] {
+            "-" => None,
+            e => Some(UNIX_EPOCH + Duration::from_secs(e.parse::<u64>().ok()?)),
+        };
+        Some(TimerState {
+            phase,
+            ends_at,
+            remaining: Duration::from_secs(f[2].parse::<u64>().ok()?),
+            urgent: f[3].parse::<bool>().ok()?,
+        })
+    }
+}
+
+fn state_file() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(d) if !d.is_empty() => PathBuf::from(d),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(dir.join("ggbar").join("timer"))
+}
+
+// $XDG_RUNTIME_DIR/ggbar-timer, e.g. `echo toggle > $XDG_RUNTIME_DIR/ggbar-timer`
+fn fifo_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(d) if !d.is_empty() => PathBuf::from(d).join("ggbar-timer"),
+        _ => PathBuf::from(format!("/tmp/ggbar-timer-{}", unsafe { libc::getuid() })),
+    }
+}
+
+fn open_fifo() -> Option<File> {
+    let path = fifo_path();
+    let c_path = CString::new(path.as_os_str().as_encoded_bytes()).ok()?;
+    unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) };
+    // opened for writing as well, so there always is a writer and reads
+    // don't return EOF after every command
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .ok()
+}
+
+extern "C" fn on_signal(sig: libc::c_int) {
+    let b = if sig == libc::SIGUSR1 { b't' } else { b'r' };
+    let fd = SIGNAL_PIPE.load(Ordering::Relaxed);
+    unsafe { libc::write(fd, &b as *const u8 as *const libc::c_void, 1) };
+}
+
+// SIGUSR1 toggles, SIGUSR2 resets. Returns the read end of the pipe the
+// handler writes to.
+fn install_signal_handlers() -> Option<File> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0 {
+        return None;
+    }
+    SIGNAL_PIPE.store(fds[1], Ordering::Relaxed);
+    let read_end = unsafe { File::from_raw_fd(fds[0]) };
+
+    // SA_RESTART, so blocking reads in other threads don't fail with EINTR
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    unsafe {
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut());
+        libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut());
+    }
+    Some(read_end)
+}
+
+impl Timer {
+    // work and break length in minutes
+    pub fn new(work: u64, brk: u64, format: &'static str) -> Timer {
+        let work = work * 60;
+        let saved = state_file()
+            .and_then(|f| std::fs::read_to_string(f).ok())
+            .and_then(|s| TimerState::deserialize(&s));
+        Timer {
+            work,
+            brk: brk * 60,
+            format,
+            state: Arc::new(Mutex::new(
+                saved.unwrap_or_else(|| TimerState::new(Phase::Work, Duration::from_secs(work))))),
+        }
+    }
+
+    fn length(work: u64, brk: u64, phase: Phase) -> Duration {
+        match phase {
+            Phase::Work => Duration::from_secs(work),
+            Phase::Break => Duration::from_secs(brk),
+        }
+    }
+
+    fn run(state: &Mutex<TimerState>, work: u64, brk: u64, cmd: Command) {
+        let now = SystemTime::now();
+        let mut s = state.lock().unwrap();
+        match cmd {
+            Command::Toggle => {
+                s.urgent = false;
+                match s.ends_at {
+                    Some(_) => {
+                        s.remaining = s.remaining(now);
+                        s.ends_at = None;
+                    }
+                    None => s.ends_at = Some(now + s.remaining),
+                }
+            }
+            Command::Reset => *s = TimerState::new(s.phase, Self::length(work, brk, s.phase)),
+            Command::Skip => {
+                let next = s.phase.next();
+                *s = TimerState::new(next, Self::length(work, brk, next));
+            }
+        }
+        Self::save(&s);
+    }
+
+    // the running phase ran out, the next one waits to be started
+    fn check_end(state: &Mutex<TimerState>, work: u64, brk: u64) -> bool {
+        let mut s = state.lock().unwrap();
+        if s.ends_at.is_none() || s.remaining(SystemTime::now()) > Duration::ZERO {
+            return false;
+        }
+        let next = s.phase.next();
+        *s = TimerState::new(next, Self::length(work, brk, next));
+        s.urgent = true;
+        Self::save(&s);
+        true
+    }
+
+    fn save(state: &TimerState) {
+        if let Some(f) = state_file() {
+            if let Some(dir) = f.parent() {
+                let _ = std::fs::create_dir_all(dir);
+            }
+            let _ = std::fs::write(f, state.serialize());
+        }
+    }
+}
+
+impl BarModule for Timer {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let s = *self.state.lock().unwrap();
+        // blinks like a tag with an urgent window until it's started again
+        let color_box = if s.urgent {
+            if animation::urgent_blink() {
+                COLOR_URGENT_WINDOW
+            } else {
+                COLOR_URGENT_WINDOW_BLINK
+            }
+        } else {
+            match s.phase {
+                Phase::Work => COLOR_BG_TIMER_WORK,
+                Phase::Break => COLOR_BG_TIMER_BREAK,
+            }
+        };
+
+        let remaining = s.remaining(SystemTime::now());
+        // rounded up, so a full minute shows as 25:00 right after starting
+        let secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        let text = format_placeholders(self.format, &[
+            ("phase", String::from(match s.phase {
+                Phase::Work => TIMER_WORK_LABEL,
+                Phase::Break => TIMER_BREAK_LABEL,
+            })),
+            ("remaining", format_mmss(secs)),
+        ]);
+        let b = CairoTextBox {
+            text,
+            height: dyn_config.height,
+            color_text: if s.urgent { COLOR_TEXT_URGENT_WINDOW } else { COLOR_TEXT },
+            color_box,
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn click(&self, _conn: &xcb::Connection, click: &Click) -> bool {
+        let cmd = match click.button {
+            BUTTON_LEFT => Command::Toggle,
+            BUTTON_MIDDLE => Command::Skip,
+            BUTTON_RIGHT => Command::Reset,
+            _ => return false,
+        };
+        Self::run(&self.state, self.work, self.brk, cmd);
+        true
+    }
+
+    fn animation(&self) -> Option<Duration> {
+        if self.state.lock().unwrap().urgent {
+            Some(animation::until_urgent_blink())
+        } else {
+            None
+        }
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        let (work, brk) = (self.work, self.brk);
+        thread::spawn(move || {
+            let signals = install_signal_handlers();
+            let fifo = open_fifo();
+            let mut buf = [0u8; 256];
+            loop {
+                // ended while the bar wasn't running, or just now
+                if Self::check_end(&state, work, brk) {
+                    signal_bar_redraw(bar_state.clone());
+                }
+
+                // wake up when the displayed second changes
+                let timeout = match state.lock().unwrap().ends_at {
+                    Some(end) => {
+                        let left = end.duration_since(SystemTime::now()).unwrap_or_default();
+                        (left.subsec_millis() as i32).max(1)
+                    }
+                    None => -1,
+                };
+                let mut pfds: Vec<libc::pollfd> = signals.iter()
+                    .chain(fifo.iter())
+                    .map(|f| libc::pollfd { fd: f.as_raw_fd(), events: libc::POLLIN, revents: 0 })
+                    .collect();
+                unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, timeout) };
+
+                let mut commands = Vec::new();
+                if let Some(mut f) = signals.as_ref() {
+                    while let Ok(n @ 1..) = f.read(&mut buf) {
+                        commands.extend(buf[..n].iter().map(|b| match b {
+                            b't' => Command::Toggle,
+                            _ => Command::Reset,
+                        }));
+                    }
+                }
+                if let Some(mut f) = fifo.as_ref() {
+                    while let Ok(n @ 1..) = f.read(&mut buf) {
+                        commands.extend(String::from_utf8_lossy(&buf[..n])
+                                            .lines()
+                                            .filter_map(parse_command));
+                    }
+                }
+                for cmd in commands {
+                    Self::run(&state, work, brk, cmd);
+                }
+                signal_bar_redraw(bar_state.clone());
+            }
+        });
+    }
+}
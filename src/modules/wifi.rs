@@ -0,0 +1,371 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use crate::netlink::{self, NetlinkSocket};
+use std::io;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::Duration;
+use super::BarModule;
+
+// see <linux/nl80211.h>
+const NL80211_CMD_GET_INTERFACE: u8 = 5;
+const NL80211_CMD_GET_STATION: u8   = 17;
+
+const NL80211_ATTR_IFINDEX: u16  = 3;
+const NL80211_ATTR_IFNAME: u16   = 4;
+const NL80211_ATTR_STA_INFO: u16 = 21;
+const NL80211_ATTR_SSID: u16     = 52;
+
+const NL80211_STA_INFO_SIGNAL: u16     = 7;
+const NL80211_STA_INFO_TX_BITRATE: u16 = 8;
+
+const NL80211_RATE_INFO_BITRATE: u16   = 1;
+const NL80211_RATE_INFO_BITRATE32: u16 = 5;
+
+pub struct Wifi {
+    // None for the first interface that is connected
+    interface: Option<&'static str>,
+    interval: u64,
+    // placeholders: {glyph} {ssid} {signal} {quality} {bitrate}
+    format: &'static str,
+    state: Arc<Mutex<Option<Link>>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Interface {
+    pub ifindex: u32,
+    pub ifname: String,
+    // only set while connected
+    pub ssid: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Station {
+    pub signal_dbm: i8,
+    // in 100 kbit/s
+    pub bitrate: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Link {
+    ssid: String,
+    station: Station,
+}
+
+// reply to NL80211_CMD_GET_INTERFACE
+pub fn parse_interface(payload: &[u8]) -> Option<Interface> {
+    let mut iface: Interface = Default::default();
+    let mut has_index = false;
+    for (t, data) in netlink::genl_attributes(payload) {
+        match t {
+            NL80211_ATTR_IFINDEX => {
+                iface.ifindex = netlink::attr_u32(data)?;
+                has_index = true;
+            }
+            NL80211_ATTR_IFNAME => {
+                let name = data.split(|b| *b == 0).next().unwrap_or(&[]);
+                iface.ifname = String::from_utf8_lossy(name).into_owned();
+            }
+            NL80211_ATTR_SSID => {
+                iface.ssid = Some(String::from_utf8_lossy(data).into_owned());
+            }
+            _ => {}
+        }
+    }
+    if has_index {
+        Some(iface)
+    } else {
+        None
+    }
+}
+
+// reply to NL80211_CMD_GET_STATION, None without signal information
+pub fn parse_station(payload: &[u8]) -> Option<Station> {
+    let sta_info = netlink::genl_attributes(payload)
+        .into_iter()
+        .find(|(t, _)| *t == NL80211_ATTR_STA_INFO)?
+        .1;
+
+    let mut signal = None;
+    let mut bitrate = None;
+    for (t, data) in netlink::attributes(sta_info) {
+        match t {
+            NL80211_STA_INFO_SIGNAL => signal = data.first().map(|s| *s as i8),
+            NL80211_STA_INFO_TX_BITRATE => {
+                for (rt, rdata) in netlink::attributes(data) {
+                    match rt {
+                        NL80211_RATE_INFO_BITRATE32 => bitrate = netlink::attr_u32(rdata),
+                        NL80211_RATE_INFO_BITRATE if bitrate.is_none() => {
+                            bitrate = netlink::attr_u16(rdata).map(u32::from);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(Station {
+        signal_dbm: signal?,
+        bitrate,
+    })
+}
+
+// signal quality in percent, -100 dBm and below is 0, -50 dBm and above 100
+pub fn quality(signal_dbm: i8) -> u32 {
+    (2 * (signal_dbm as i32 + 100)).clamp(0, 100) as u32
+}
+
+fn query(interface: Option<&str>) -> io::Result<Option<Link>> {
+    let sock = NetlinkSocket::open(libc::NETLINK_GENERIC, 0)?;
+    let family = netlink::resolve_family(&sock, "nl80211")?;
+
+    let dump = netlink::NLM_F_REQUEST | netlink::NLM_F_DUMP;
+    let msg = netlink::genl_message(family, NL80211_CMD_GET_INTERFACE, dump, 2, &[]);
+    let iface = sock.request(&msg)?
+        .iter()
+        .filter_map(|p| parse_interface(p))
+        .find(|i| match interface {
+            Some(name) => i.ifname == name,
+            None => i.ssid.is_some(),
+        });
+    let (ifindex, ssid) = match iface {
+        Some(Interface { ifindex, ssid: Some(ssid), .. }) => (ifindex, ssid),
+        _ => return Ok(None),
+    };
+
+    let attrs = netlink::attribute(NL80211_ATTR_IFINDEX, &ifindex.to_ne_bytes());
+    let msg = netlink::genl_message(family, NL80211_CMD_GET_STATION, dump, 3, &attrs);
+    let station = sock.request(&msg)?
+        .iter()
+        .find_map(|p| parse_station(p));
+    Ok(station.map(|station| Link { ssid, station }))
+}
+
+impl Wifi {
+    pub fn new(interface: Option<&'static str>, interval: u64, format: &'static str) -> Wifi {
+        Wifi {
+            interface,
+            interval,
+            format,
+            state: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl BarModule for Wifi {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let (text, color_box) = match &*self.state.lock().unwrap() {
+            Some(link) => {
+                let q = quality(link.station.signal_dbm);
+                let glyph = WIFI_SIGNAL_GLYPHS[q as usize * WIFI_SIGNAL_GLYPHS.len() / 101];
+                let bitrate = link.station.bitrate
+                    .map_or(String::from("-"), |b| format!("{:.0}Mb/s", b as f64 / 10.0));
+                let text = format_placeholders(self.format, &[
+                    ("glyph", String::from(glyph)),
+                    ("ssid", link.ssid.clone()),
+                    ("signal", format!("{}dBm", link.station.signal_dbm)),
+                    ("quality", format!("{}%", q)),
+                    ("bitrate", bitrate),
+                ]);
+                (text, COLOR_BG_WIFI)
+            }
+            None => (String::from(WIFI_DISCONNECTED), COLOR_BG_WIFI_DOWN),
+        };
+
+        let b = CairoTextBox {
+            text,
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box,
+            alignment: Alignment::Right,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let state = self.state.clone();
+        let interface = self.interface;
+        let interval = self.interval;
+        thread::spawn(move || {
+            loop {
+                *state.lock().unwrap() = query(interface).unwrap_or(None);
+                signal_bar_redraw(bar_state.clone());
+                thread::sleep(Duration::from_millis(interval));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlink::collect_replies;
+
+    // replies as an x86_64 kernel sends them for `iw dev` and `iw dev wlp2s0
+    // station dump`, netlink is in host byte order. nl80211 is family 0x1c.
+
+    // first datagram of the GET_INTERFACE dump, seq 2: wlp2s0 connected to
+    // "home net" at 5180 MHz and the P2P device of the same phy, which
+    // has no netdev and so no ifindex
+    const INTERFACES: [u8; 300] = [
+        0xdc, 0x00, 0x00, 0x00, 0x1c, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x52, 0x3d, 0x00, 0x00, 0x07, 0x01, 0x00, 0x00, 0x08, 0x00, 0x03, 0x00,
+        0x03, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x04, 0x00, 0x77, 0x6c, 0x70, 0x32,
+        0x73, 0x30, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0xf5, 0x00,
+        0x0c, 0x00, 0x99, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x0a, 0x00, 0x06, 0x00, 0x5c, 0x87, 0x9c, 0x1a, 0x2b, 0x3c, 0x00, 0x00,
+        0x08, 0x00, 0x2e, 0x00, 0x07, 0x00, 0x00, 0x00, 0x05, 0x00, 0x53, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x26, 0x00, 0x3c, 0x14, 0x00, 0x00,
+        0x08, 0x00, 0x27, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x9f, 0x00,
+        0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0xa0, 0x00, 0x5a, 0x14, 0x00, 0x00,
+        0x08, 0x00, 0x62, 0x00, 0xd0, 0x07, 0x00, 0x00, 0x0c, 0x00, 0x34, 0x00,
+        0x68, 0x6f, 0x6d, 0x65, 0x20, 0x6e, 0x65, 0x74, 0x44, 0x00, 0x09, 0x81,
+        0x08, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x02, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x1c, 0x00, 0x02, 0x00,
+        0x02, 0x00, 0x00, 0x00, 0x52, 0x3d, 0x00, 0x00, 0x07, 0x01, 0x00, 0x00,
+        0x08, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00,
+        0x0a, 0x00, 0x00, 0x00, 0x04, 0x00, 0xf5, 0x00, 0x0c, 0x00, 0x99, 0x00,
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x06, 0x00,
+        0x5e, 0x87, 0x9c, 0x1a, 0x2b, 0x3c, 0x00, 0x00, 0x08, 0x00, 0x2e, 0x00,
+        0x07, 0x00, 0x00, 0x00, 0x05, 0x00, 0x53, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    // second datagram, NLMSG_DONE
+    const INTERFACES_DONE: [u8; 20] = [
+        0x14, 0x00, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x52, 0x3d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    // GET_STATION dump for ifindex 3, seq 3: the AP at -58 dBm, VHT MCS 9
+    // 2SS at 80 MHz short GI (866.7 Mb/s), then NLMSG_DONE
+    const STATION: [u8; 288] = [
+        0x0c, 0x01, 0x00, 0x00, 0x1c, 0x00, 0x02, 0x00, 0x03, 0x00, 0x00, 0x00,
+        0x52, 0x3d, 0x00, 0x00, 0x13, 0x01, 0x00, 0x00, 0x08, 0x00, 0x03, 0x00,
+        0x03, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x06, 0x00, 0xa4, 0xb1, 0xc2, 0xd3,
+        0xe4, 0xf5, 0x00, 0x00, 0x08, 0x00, 0x2e, 0x00, 0x0c, 0x00, 0x00, 0x00,
+        0xdc, 0x00, 0x15, 0x00, 0x08, 0x00, 0x01, 0x00, 0x78, 0x00, 0x00, 0x00,
+        0x04, 0x00, 0x21, 0x00, 0x0c, 0x00, 0x17, 0x00, 0x7b, 0xad, 0xdf, 0x02,
+        0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x21, 0x00, 0x0c, 0x00, 0x18, 0x00,
+        0x8e, 0x10, 0x31, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x07, 0x00,
+        0xc6, 0x00, 0x00, 0x00, 0x05, 0x00, 0x0d, 0x00, 0xc7, 0x00, 0x00, 0x00,
+        0x14, 0x00, 0x13, 0x00, 0x05, 0x00, 0x00, 0x00, 0xc4, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x01, 0x00, 0xc3, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x08, 0x00,
+        0x08, 0x00, 0x05, 0x00, 0xdb, 0x21, 0x00, 0x00, 0x06, 0x00, 0x01, 0x00,
+        0xdb, 0x21, 0x00, 0x00, 0x05, 0x00, 0x06, 0x00, 0x09, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x07, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x08, 0x00,
+        0x04, 0x00, 0x04, 0x00, 0x2c, 0x00, 0x0e, 0x00, 0x08, 0x00, 0x05, 0x00,
+        0x78, 0x1e, 0x00, 0x00, 0x06, 0x00, 0x01, 0x00, 0x78, 0x1e, 0x00, 0x00,
+        0x05, 0x00, 0x06, 0x00, 0x08, 0x00, 0x00, 0x00, 0x05, 0x00, 0x07, 0x00,
+        0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x08, 0x00, 0x04, 0x00, 0x04, 0x00,
+        0x08, 0x00, 0x09, 0x00, 0x27, 0x9d, 0x00, 0x00, 0x08, 0x00, 0x0a, 0x00,
+        0x46, 0x32, 0x00, 0x00, 0x08, 0x00, 0x0b, 0x00, 0x1f, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x00,
+        0x10, 0x0e, 0x00, 0x00, 0x0c, 0x00, 0x11, 0x00, 0x7e, 0x00, 0x00, 0x00,
+        0x62, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00,
+        0x03, 0x00, 0x00, 0x00, 0x52, 0x3d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    // GET_STATION for ifindex 9, which doesn't exist: -ENODEV and the
+    // request echoed back
+    const STATION_ERROR: [u8; 48] = [
+        0x30, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+        0x52, 0x3d, 0x00, 0x00, 0xed, 0xff, 0xff, 0xff, 0x1c, 0x00, 0x00, 0x00,
+        0x1c, 0x00, 0x01, 0x03, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x11, 0x01, 0x00, 0x00, 0x08, 0x00, 0x03, 0x00, 0x09, 0x00, 0x00, 0x00,
+    ];
+
+    // payloads of the replies to `seq` in the datagrams
+    fn replies(datagrams: &[&[u8]], seq: u32) -> io::Result<Vec<Vec<u8>>> {
+        let mut replies = Vec::new();
+        for d in datagrams {
+            if let Some(r) = collect_replies(d, seq, &mut replies) {
+                return r.map(|_| replies);
+            }
+        }
+        panic!("request not answered");
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn interface_dump() {
+        let r = replies(&[&INTERFACES, &INTERFACES_DONE], 2).unwrap();
+        assert_eq!(r.len(), 2);
+        assert_eq!(parse_interface(&r[0]), Some(Interface {
+            ifindex: 3,
+            ifname: String::from("wlp2s0"),
+            ssid: Some(String::from("home net")),
+        }));
+        assert_eq!(parse_interface(&r[1]), None);
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn interface_truncated() {
+        let r = replies(&[&INTERFACES, &INTERFACES_DONE], 2).unwrap();
+        // cut off in the ssid attribute, it's dropped
+        let at = r[0].windows(8).position(|w| w == b"home net").unwrap();
+        let iface = parse_interface(&r[0][..at + 4]).unwrap();
+        assert_eq!(iface.ifname, "wlp2s0");
+        assert_eq!(iface.ssid, None);
+        // only the genl header
+        assert_eq!(parse_interface(&r[0][..4]), None);
+        assert_eq!(parse_interface(&[]), None);
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn station() {
+        let r = replies(&[&STATION], 3).unwrap();
+        assert_eq!(r.len(), 1);
+        let station = parse_station(&r[0]).unwrap();
+        assert_eq!(station, Station {
+            signal_dbm: -58,
+            bitrate: Some(8667),
+        });
+        assert_eq!(quality(station.signal_dbm), 84);
+        // station info cut off
+        assert_eq!(parse_station(&r[0][..r[0].len() - 8]), None);
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn station_error() {
+        let err = replies(&[&STATION_ERROR], 3).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENODEV));
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn replies_of_other_requests() {
+        // the interface dump is seq 2, a request with seq 3 waits on
+        let mut r = Vec::new();
+        assert!(collect_replies(&INTERFACES, 3, &mut r).is_none());
+        assert!(collect_replies(&INTERFACES_DONE, 3, &mut r).is_none());
+        assert!(r.is_empty());
+        assert!(collect_replies(&STATION, 3, &mut r).is_some());
+        assert_eq!(r.len(), 1);
+        // an error reply cut off before the errno
+        let mut error = STATION_ERROR[..19].to_vec();
+        error[0] = 19;
+        assert_eq!(replies(&[&error], 3).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn signal_quality() {
+        assert_eq!(quality(-100), 0);
+        assert_eq!(quality(-128), 0);
+        assert_eq!(quality(-50), 100);
+        assert_eq!(quality(-20), 100);
+    }
+}
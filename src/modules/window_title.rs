@@ -0,0 +1,244 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::monitors;
+use crate::config::*;
+use crate::utils::*;
+use std::sync::{Arc, Mutex, Condvar};
+use std::process::Command;
+use super::BarModule;
+
+pub enum Ellipsize {
+    Start,
+    Middle,
+    End,
+}
+
+pub struct WindowTitle {
+    max_length: usize,
+    ellipsize: Ellipsize,
+    only_this_monitor: bool,
+    state: Mutex<TitleState>,
+}
+
+#[derive(Default)]
+struct TitleState {
+    atoms: Option<Atoms>,
+    // first event code of RandR, None without the extension
+    randr_event: Option<u8>,
+    active: xcb::Window,
+    title: Option<String>,
+    // center of the active window in root coordinates
+    center: (i32, i32),
+    // (monitor, (x, y, w, h)) of the hlwm monitors, read on first use and
+    // again after RandR changes
+    monitor_rects: Vec<(i32, (i32, i32, i32, i32))>,
+}
+
+struct Atoms {
+    root: xcb::Window,
+    net_active_window: xcb::Atom,
+    net_wm_name: xcb::Atom,
+    utf8_string: xcb::Atom,
+}
+
+impl WindowTitle {
+    pub fn new(max_length: usize, ellipsize: Ellipsize, only_this_monitor: bool) -> WindowTitle {
+        WindowTitle {
+            max_length,
+            ellipsize,
+            only_this_monitor,
+            state: Mutex::new(Default::default()),
+        }
+    }
+
+    fn ellipsized(&self, title: &str) -> String {
+        let chars: Vec<char> = title.chars().collect();
+        if chars.len() <= self.max_length {
+            return String::from(title);
+        }
+        let keep = self.max_length.saturating_sub(1);
+        let s: String = match self.ellipsize {
+            Ellipsize::Start => chars[chars.len() - keep..].iter().collect(),
+            Ellipsize::End => chars[..keep].iter().collect(),
+            Ellipsize::Middle => {
+                let head: String = chars[..keep - keep / 2].iter().collect();
+                let tail: String = chars[chars.len() - keep / 2..].iter().collect();
+                return format!("{}{}{}", head, WINDOW_TITLE_ELLIPSIS, tail);
+            }
+        };
+        match self.ellipsize {
+            Ellipsize::Start => format!("{}{}", WINDOW_TITLE_ELLIPSIS, s),
+            _ => format!("{}{}", s, WINDOW_TITLE_ELLIPSIS),
+        }
+    }
+
+    // switch to a newly focused client and start following its title
+    fn update_active(conn: &xcb::Connection, state: &mut TitleState) {
+        let atoms = match &state.atoms {
+            Some(a) => a,
+            None => return,
+        };
+        let active = xcb::get_property(conn, false, atoms.root, atoms.net_active_window,
+                                       xcb::ATOM_WINDOW, 0, 1)
+            .get_reply()
+            .ok()
+            .and_then(|r| r.value::<u32>().first().cloned())
+            .unwrap_or(0);
+
+        if active != state.active {
+            if state.active != 0 {
+                unwatch_window(conn, state.active);
+            }
+            if active != 0 {
+                watch_window(conn, active);
+            }
+            conn.flush();
+        }
+
+        state.center = Self::read_center(conn, atoms.root, active);
+        state.title = Self::read_title(conn, atoms, active);
+        state.active = active;
+    }
+
+    fn read_title(conn: &xcb::Connection, atoms: &Atoms, win: xcb::Window) -> Option<String> {
+        if win == 0 {
+            return None;
+        }
+        let net_wm_name = xcb::get_property(conn, false, win, atoms.net_wm_name,
+                                            atoms.utf8_string, 0, 1024)
+            .get_reply()
+            .ok()
+            .filter(|r| r.value_len() > 0)
+            .map(|r| String::from_utf8_lossy(r.value::<u8>()).into_owned());
+        if net_wm_name.is_some() {
+            return net_wm_name;
+        }
+        xcb::get_property(conn, false, win, xcb::ATOM_WM_NAME, xcb::ATOM_ANY, 0, 1024)
+            .get_reply()
+            .ok()
+            .filter(|r| r.value_len() > 0 && r.format() == 8)
+            .map(|r| String::from_utf8_lossy(r.value::<u8>()).into_owned())
+    }
+
+    fn read_center(conn: &xcb::Connection, root: xcb::Window, win: xcb::Window) -> (i32, i32) {
+        if win == 0 {
+            return (0, 0);
+        }
+        let geometry = xcb::get_geometry(conn, win).get_reply();
+        let position = xcb::translate_coordinates(conn, win, root, 0, 0).get_reply();
+        match (geometry, position) {
+            (Ok(g), Ok(p)) => (p.dst_x() as i32 + g.width() as i32 / 2,
+                               p.dst_y() as i32 + g.height() as i32 / 2),
+            _ => (0, 0),
+        }
+    }
+
+    fn read_monitor_rect(monitor: i32) -> Option<(i32, i32, i32, i32)> {
+        let hc_output = Command::new("herbstclient")
+            .arg("monitor_rect")
+            .arg(monitor.to_string())
+            .output()
+            .ok()?;
+        let out = String::from_utf8_lossy(&hc_output.stdout);
+        let r: Vec<i32> = out.split_whitespace()
+            .filter_map(|v| v.parse::<i32>().ok())
+            .collect();
+        if r.len() != 4 {
+            return None;
+        }
+        Some((r[0], r[1], r[2], r[3]))
+    }
+
+    // returns true if the title, or where it's shown, may have changed
+    fn handle_event(conn: &xcb::Connection, state: &mut TitleState,
+                    event: &xcb::GenericEvent) -> bool {
+        let r = event.response_type() & !0x80;
+        // screen or crtc change, monitors may have moved
+        if state.randr_event.is_some_and(|e| r == e || r == e + 1) {
+            state.monitor_rects.clear();
+            return true;
+        }
+        if r != xcb::PROPERTY_NOTIFY {
+            return false;
+        }
+        let ev: &xcb::PropertyNotifyEvent = unsafe { xcb::cast_event(event) };
+        let atoms = match &state.atoms {
+            Some(a) => a,
+            None => return false,
+        };
+
+        if ev.window() == atoms.root && ev.atom() == atoms.net_active_window {
+            Self::update_active(conn, state);
+            true
+        } else if ev.window() == state.active && ev.window() != 0
+            && (ev.atom() == atoms.net_wm_name || ev.atom() == xcb::ATOM_WM_NAME) {
+            state.title = Self::read_title(conn, atoms, state.active);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl BarModule for WindowTitle {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        let title = match &state.title {
+            Some(t) if !t.is_empty() => t.clone(),
+            _ => return align,
+        };
+
+        if self.only_this_monitor {
+            let monitor = dyn_config.monitor;
+            if !state.monitor_rects.iter().any(|(m, _)| *m == monitor) {
+                if let Some(rect) = Self::read_monitor_rect(monitor) {
+                    state.monitor_rects.push((monitor, rect));
+                }
+            }
+            let rect = state.monitor_rects.iter().find(|(m, _)| *m == monitor).map(|(_, r)| *r);
+            if let Some((x, y, w, h)) = rect {
+                let (cx, cy) = state.center;
+                if cx < x || cx >= x + w || cy < y || cy >= y + h {
+                    return align;
+                }
+            }
+        }
+
+        let b = CairoTextBox {
+            text: self.ellipsized(&title),
+            height: dyn_config.height,
+            color_text: COLOR_TEXT,
+            color_box: COLOR_BG_WINDOW_TITLE,
+            alignment: Alignment::Left,
+            align,
+            margin: BLOCK_MARGIN,
+        };
+        b.draw(cairo, dyn_config.font)
+    }
+
+    fn event_generator(&self, _bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        // no op, updates come in through x_event
+    }
+
+    fn x_init(&self, conn: &xcb::Connection, screen_num: i32) {
+        let setup = conn.get_setup();
+        let root = setup.roots().nth(screen_num as usize).unwrap().root();
+        xcb::change_window_attributes(conn, root, &[
+            (xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)
+        ]);
+
+        let mut state = self.state.lock().unwrap();
+        state.atoms = Some(Atoms {
+            root,
+            net_active_window: intern_atom(conn, "_NET_ACTIVE_WINDOW"),
+            net_wm_name: intern_atom(conn, "_NET_WM_NAME"),
+            utf8_string: intern_atom(conn, "UTF8_STRING"),
+        });
+        state.randr_event = monitors::watch(conn, root);
+        Self::update_active(conn, &mut state);
+    }
+
+    fn x_event(&self, conn: &xcb::Connection, event: &xcb::GenericEvent) -> bool {
+        Self::handle_event(conn, &mut self.state.lock().unwrap(), event)
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{CairoTextBox, DynamicConfig, Alignment};
+use crate::BarState;
+use crate::config::*;
+use crate::utils::*;
+use crate::decoration;
+use crate::animation;
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::Duration;
+use super::BarModule;
+
+pub mod herbstluftwm;
+// backends config.rs may leave out are allowed to be unused
+#[allow(dead_code)]
+pub mod bspwm;
+#[allow(dead_code)]
+pub mod i3;
+#[allow(dead_code)]
+pub mod ewmh;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagState {
+    Empty,
+    NonEmpty,
+    ThisMonitorUnfocused,
+    ThisMonitorFocused,
+    DifferentMonitorUnfocused,
+    DifferentMonitorFocused,
+    UrgentWindow,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tag {
+    pub state: TagState,
+    pub name: String,
+    // number of clients, if the backend knows it
+    pub clients: Option<usize>,
+}
+
+// source of the tags shown by the workspaces module
+pub trait WorkspaceBackend {
+    // tags in display order, as seen from the given monitor. client counts
+    // only need to be filled in if `client_counts` is set.
+    fn tags(&self, monitor: i32, client_counts: bool) -> Vec<Tag>;
+    // whether a tag on any monitor is shown as urgent
+    fn urgent(&self) -> bool;
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>);
+
+    fn x_init(&self, _conn: &xcb::Connection, _screen_num: i32) {}
+
+    fn x_event(&self, _conn: &xcb::Connection, _event: &xcb::GenericEvent) -> bool {
+        false
+    }
+}
+
+impl TagState {
+    fn color(&self) -> u32 {
+        match self {
+            TagState::UrgentWindow if !animation::urgent_blink() => {
+                COLOR_URGENT_WINDOW_BLINK
+            }
+            TagState::Empty => COLOR_EMPTY,
+            TagState::NonEmpty => COLOR_NON_EMPTY,
+            TagState::ThisMonitorUnfocused => COLOR_THIS_MONITOR_UNFOCUSED,
+            TagState::ThisMonitorFocused => COLOR_THIS_MONITOR_FOCUSED,
+            TagState::DifferentMonitorUnfocused => COLOR_DIFFERENT_MONITOR_UNFOCUSED,
+            TagState::DifferentMonitorFocused => COLOR_DIFFERENT_MONITOR_FOCUSED,
+            TagState::UrgentWindow => COLOR_URGENT_WINDOW,
+        }
+    }
+
+    fn text_color(&self) -> u32 {
+        match self {
+            TagState::Empty => COLOR_TEXT_EMPTY,
+            TagState::NonEmpty => COLOR_TEXT_NON_EMPTY,
+            TagState::ThisMonitorUnfocused => COLOR_TEXT_THIS_MONITOR_UNFOCUSED,
+            TagState::ThisMonitorFocused => COLOR_TEXT_THIS_MONITOR_FOCUSED,
+            TagState::DifferentMonitorUnfocused => COLOR_TEXT_DIFFERENT_MONITOR_UNFOCUSED,
+            TagState::DifferentMonitorFocused => COLOR_TEXT_DIFFERENT_MONITOR_FOCUSED,
+            TagState::UrgentWindow => COLOR_TEXT_URGENT_WINDOW,
+        }
+    }
+}
+
+pub struct Workspaces {
+    backend: Box<dyn WorkspaceBackend>,
+    // (tag name, displayed label) pairs, other tags keep their name
+    tag_labels: Vec<(&'static str, &'static str)>,
+    // don't draw tags without clients
+    hide_empty: bool,
+    // number of clients on each tag as a superscript
+    client_counts: bool,
+}
+
+impl Workspaces {
+    pub fn new(backend: Box<dyn WorkspaceBackend>,
+               tag_labels: Vec<(&'static str, &'static str)>,
+               hide_empty: bool,
+               client_counts: bool) -> Workspaces {
+        Workspaces {
+            backend,
+            tag_labels,
+            hide_empty,
+            client_counts,
+        }
+    }
+
+    fn label(&self, tag: &Tag) -> String {
+        self.tag_labels.iter()
+            .find(|(name, _)| *name == tag.name)
+            .map_or_else(|| tag.name.clone(), |(_, label)| String::from(*label))
+    }
+}
+
+impl BarModule for Workspaces {
+    fn render(&self, dyn_config: DynamicConfig, cairo: &cairo::Context, align: f64) -> f64 {
+        let tags = self.backend.tags(dyn_config.monitor, self.client_counts);
+
+        // monitor focus status, a block of its own the first tag follows
+        let mut focus_color = COLOR_MONITOR_UNFOCUSED;
+        for t in tags.iter() {
+            if t.state == TagState::ThisMonitorFocused {
+                focus_color = COLOR_MONITOR_FOCUSED;
+            }
+        }
+        let notched = decoration::notched(false, align);
+        let w_notch = if notched { decoration::overhang(dyn_config.height) } else { 0.0 };
+        let focus = decoration::Block {
+            right: false,
+            lead: align,
+            width: MONITOR_FOCUS_SIZE * dyn_config.height + w_notch,
+            height: dyn_config.height,
+            notched,
+        };
+        focus.draw(cairo, focus_color);
+
+        // tags
+        let mut left_border: f64 = align + focus.width + TAG_SPACE;
+        for t in tags.iter() {
+            if self.hide_empty && t.state == TagState::Empty {
+                continue;
+            }
+            let mut text = self.label(t);
+            if let Some(c) = t.clients.filter(|c| self.client_counts && *c > 0) {
+                text.push_str(&superscript(c));
+            }
+            let b = CairoTextBox {
+                text,
+                height: dyn_config.height,
+                color_text: t.state.text_color(),
+                color_box: t.state.color(),
+                alignment: Alignment::Left,
+                align: left_border,
+                margin: TAG_MARGIN,
+            };
+            let new_left = b.draw(cairo, dyn_config.clone().font);
+            left_border = new_left + TAG_SPACE;
+        }
+        left_border
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        self.backend.event_generator(bar_state);
+    }
+
+    fn x_init(&self, conn: &xcb::Connection, screen_num: i32) {
+        self.backend.x_init(conn, screen_num);
+    }
+
+    fn x_event(&self, conn: &xcb::Connection, event: &xcb::GenericEvent) -> bool {
+        self.backend.x_event(conn, event)
+    }
+
+    fn animation(&self) -> Option<Duration> {
+        if self.backend.urgent() {
+            Some(animation::until_urgent_blink())
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::BarState;
+use crate::utils::*;
+use std::sync::{Arc, Mutex, Condvar};
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::thread;
+use super::{Tag, TagState, WorkspaceBackend};
+
+// desktops from `bspc subscribe report`, one list per monitor
+pub struct Bspwm {
+    monitors: Arc<Mutex<Vec<Vec<Tag>>>>,
+}
+
+impl Bspwm {
+    pub fn new() -> Bspwm {
+        Bspwm {
+            monitors: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+// desktops of every monitor, in report order, from one report line like
+// `WMeDP-1:OI:oII:fIII:LT:TT:G:mHDMI-1:FIV:LT:TT:G`
+pub fn parse_report(line: &str) -> Vec<Vec<Tag>> {
+    let mut monitors: Vec<Vec<Tag>> = Vec::new();
+    let line = match line.strip_prefix('W') {
+        Some(l) => l,
+        None => return monitors,
+    };
+
+    let mut monitor_focused = false;
+    for item in line.split(':') {
+        let mut chars = item.chars();
+        let sym = match chars.next() {
+            Some(c) => c,
+            None => continue,
+        };
+        let name = String::from(chars.as_str());
+
+        // a focused desktop is the one shown on its monitor
+        let shown = if monitor_focused {
+            TagState::ThisMonitorFocused
+        } else {
+            TagState::ThisMonitorUnfocused
+        };
+        let state = match sym {
+            'M' | 'm' => {
+                monitor_focused = sym == 'M';
+                monitors.push(Vec::new());
+                continue;
+            }
+            'O' | 'F' | 'U' => shown,
+            'o' => TagState::NonEmpty,
+            'f' => TagState::Empty,
+            'u' => TagState::UrgentWindow,
+            // layout, state and flags of the focused node
+            _ => continue,
+        };
+        if let Some(m) = monitors.last_mut() {
+            m.push(Tag {
+                state,
+                name,
+                clients: None,
+            });
+        }
+    }
+    monitors
+}
+
+// keep `monitors` up to date from a stream of report lines
+pub fn read_reports<R: BufRead>(reader: R,
+                                monitors: &Mutex<Vec<Vec<Tag>>>,
+                                on_change: &dyn Fn()) {
+    lossy_lines(reader)
+        .for_each(|line| {
+            let m = parse_report(&line);
+            if m.is_empty() {
+                return;
+            }
+            *monitors.lock().unwrap() = m;
+            on_change();
+        });
+}
+
+impl WorkspaceBackend for Bspwm {
+    fn tags(&self, monitor: i32, _client_counts: bool) -> Vec<Tag> {
+        let monitors = self.monitors.lock().unwrap();
+        monitors.get(monitor as usize).cloned().unwrap_or_default()
+    }
+
+    fn urgent(&self) -> bool {
+        self.monitors.lock().unwrap()
+            .iter()
+            .flatten()
+            .any(|t| t.state == TagState::UrgentWindow)
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let monitors = self.monitors.clone();
+        thread::spawn(move || {
+            loop {
+                let bspc_output = Command::new("bspc")
+                    .arg("subscribe")
+                    .arg("report")
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .expect("failed to execute command")
+                    .stdout
+                    .expect("failed to execute command");
+                read_reports(BufReader::new(bspc_output), &monitors, &|| {
+                    signal_bar_redraw(bar_state.clone())
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn tag(state: TagState, name: &str) -> Tag {
+        Tag { state, name: String::from(name), clients: None }
+    }
+
+    #[test]
+    fn report_two_monitors() {
+        let m = parse_report("WMeDP-1:OI:oII:fIII:uIV:LT:TT:G:mHDMI-1:FV:LT:TT:G");
+        assert_eq!(m, vec![
+            vec![
+                tag(TagState::ThisMonitorFocused, "I"),
+                tag(TagState::NonEmpty, "II"),
+                tag(TagState::Empty, "III"),
+                tag(TagState::UrgentWindow, "IV"),
+            ],
+            vec![tag(TagState::ThisMonitorUnfocused, "V")],
+        ]);
+    }
+
+    #[test]
+    fn report_focused_urgent_desktop() {
+        let m = parse_report("WmeDP-1:oI:UII:LM:TF:G");
+        assert_eq!(m, vec![vec![
+            tag(TagState::NonEmpty, "I"),
+            tag(TagState::ThisMonitorUnfocused, "II"),
+        ]]);
+    }
+
+    #[test]
+    fn report_without_prefix() {
+        assert!(parse_report("").is_empty());
+        assert!(parse_report("MeDP-1:OI").is_empty());
+        // desktops before any monitor have nowhere to go
+        assert!(parse_report("WOI:oII").is_empty());
+    }
+
+    #[test]
+    fn reports_from_stream() {
+        let stream: &[u8] = b"WMeDP-1:OI:fII:LT:TT:G\n\
+                              garbage\n\
+                              WMeDP-1:fI:OII:LT:TT:G\n";
+        let monitors = Mutex::new(Vec::new());
+        let changes = Cell::new(0);
+        read_reports(stream, &monitors, &|| changes.set(changes.get() + 1));
+
+        assert_eq!(changes.get(), 2);
+        assert_eq!(*monitors.lock().unwrap(), vec![vec![
+            tag(TagState::Empty, "I"),
+            tag(TagState::ThisMonitorFocused, "II"),
+        ]]);
+    }
+
+    #[test]
+    fn reports_past_invalid_utf8() {
+        let stream: &[u8] = b"WMeDP-1:O\xffI:LT:TT:G\nWMeDP-1:OII:LT:TT:G\n";
+        let monitors = Mutex::new(Vec::new());
+        read_reports(stream, &monitors, &|| {});
+        assert_eq!(*monitors.lock().unwrap(),
+                   vec![vec![tag(TagState::ThisMonitorFocused, "II")]]);
+    }
+}
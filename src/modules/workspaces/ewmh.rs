@@ -0,0 +1,243 @@
+use crate::BarState;
+use crate::utils::*;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, Condvar};
+use super::{Tag, TagState, WorkspaceBackend};
+
+// desktops of any EWMH compliant window manager, read from root window
+// properties on the bar's X connection
+pub struct Ewmh {
+    state: Mutex<EwmhState>,
+}
+
+#[derive(Default)]
+struct EwmhState {
+    atoms: Option<Atoms>,
+    tags: Vec<Tag>,
+    // clients we already listen to for desktop and urgency changes
+    watched: HashSet<xcb::Window>,
+}
+
+struct Atoms {
+    root: xcb::Window,
+    number_of_desktops: xcb::Atom,
+    current_desktop: xcb::Atom,
+    desktop_names: xcb::Atom,
+    client_list: xcb::Atom,
+    wm_desktop: xcb::Atom,
+    wm_state: xcb::Atom,
+    demands_attention: xcb::Atom,
+    utf8_string: xcb::Atom,
+}
+
+// client on a desktop, 0xffffffff for clients on all desktops
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Client {
+    pub desktop: u32,
+    pub urgent: bool,
+}
+
+impl Ewmh {
+    pub fn new() -> Ewmh {
+        Ewmh {
+            state: Mutex::new(Default::default()),
+        }
+    }
+}
+
+// tags from the root window properties, `names` is the raw, NUL separated
+// _NET_DESKTOP_NAMES
+pub fn tags_from_desktops(number: u32, current: u32, names: &[u8], clients: &[Client]) -> Vec<Tag> {
+    let names: Vec<String> = names.split(|b| *b == 0)
+        .map(|n| String::from_utf8_lossy(n).into_owned())
+        .collect();
+
+    (0..number)
+        .map(|d| {
+            let on_desktop = clients.iter().filter(|c| c.desktop == d);
+            let count = on_desktop.clone().count();
+            let state = if d == current {
+                TagState::ThisMonitorFocused
+            } else if on_desktop.clone().any(|c| c.urgent) {
+                TagState::UrgentWindow
+            } else if count > 0 {
+                TagState::NonEmpty
+            } else {
+                TagState::Empty
+            };
+            let name = match names.get(d as usize) {
+                Some(n) if !n.is_empty() => n.clone(),
+                _ => (d + 1).to_string(),
+            };
+            Tag {
+                state,
+                name,
+                clients: Some(count),
+            }
+        })
+        .collect()
+}
+
+fn property<T: Clone>(conn: &xcb::Connection, win: xcb::Window, atom: xcb::Atom,
+                      type_: xcb::Atom) -> Vec<T> {
+    match xcb::get_property(conn, false, win, atom, type_, 0, 4096).get_reply() {
+        Ok(r) => r.value::<T>().to_vec(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn read_client(conn: &xcb::Connection, atoms: &Atoms, win: xcb::Window) -> Client {
+    let desktop = property::<u32>(conn, win, atoms.wm_desktop, xcb::ATOM_CARDINAL)
+        .first()
+        .cloned()
+        .unwrap_or(0xffffffff);
+    let demands_attention = property::<xcb::Atom>(conn, win, atoms.wm_state, xcb::ATOM_ATOM)
+        .contains(&atoms.demands_attention);
+    // urgency hint in WM_HINTS.flags
+    let urgency_hint = property::<u32>(conn, win, xcb::ATOM_WM_HINTS, xcb::ATOM_WM_HINTS)
+        .first()
+        .is_some_and(|flags| flags & (1 << 8) != 0);
+    Client {
+        desktop,
+        urgent: demands_attention || urgency_hint,
+    }
+}
+
+fn refresh(conn: &xcb::Connection, state: &mut EwmhState) {
+    let atoms = match &state.atoms {
+        Some(a) => a,
+        None => return,
+    };
+    let card = |atom| {
+        property::<u32>(conn, atoms.root, atom, xcb::ATOM_CARDINAL)
+            .first()
+            .cloned()
+            .unwrap_or(0)
+    };
+    let number = card(atoms.number_of_desktops);
+    let current = card(atoms.current_desktop);
+    let names = property::<u8>(conn, atoms.root, atoms.desktop_names, atoms.utf8_string);
+    let windows = property::<xcb::Window>(conn, atoms.root, atoms.client_list, xcb::ATOM_WINDOW);
+
+    let watched: HashSet<xcb::Window> = windows.iter().cloned().collect();
+    for w in watched.difference(&state.watched) {
+        watch_window(conn, *w);
+    }
+    for w in state.watched.difference(&watched) {
+        unwatch_window(conn, *w);
+    }
+    conn.flush();
+
+    let clients: Vec<Client> = windows.iter()
+        .map(|w| read_client(conn, atoms, *w))
+        .collect();
+    state.tags = tags_from_desktops(number, current, &names, &clients);
+    state.watched = watched;
+}
+
+impl WorkspaceBackend for Ewmh {
+    fn tags(&self, _monitor: i32, _client_counts: bool) -> Vec<Tag> {
+        self.state.lock().unwrap().tags.clone()
+    }
+
+    fn urgent(&self) -> bool {
+        self.state.lock().unwrap().tags.iter().any(|t| t.state == TagState::UrgentWindow)
+    }
+
+    fn event_generator(&self, _bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        // no op, updates come in through x_event
+    }
+
+    fn x_init(&self, conn: &xcb::Connection, screen_num: i32) {
+        let setup = conn.get_setup();
+        let root = setup.roots().nth(screen_num as usize).unwrap().root();
+        xcb::change_window_attributes(conn, root, &[
+            (xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)
+        ]);
+
+        let mut state = self.state.lock().unwrap();
+        state.atoms = Some(Atoms {
+            root,
+            number_of_desktops: intern_atom(conn, "_NET_NUMBER_OF_DESKTOPS"),
+            current_desktop: intern_atom(conn, "_NET_CURRENT_DESKTOP"),
+            desktop_names: intern_atom(conn, "_NET_DESKTOP_NAMES"),
+            client_list: intern_atom(conn, "_NET_CLIENT_LIST"),
+            wm_desktop: intern_atom(conn, "_NET_WM_DESKTOP"),
+            wm_state: intern_atom(conn, "_NET_WM_STATE"),
+            demands_attention: intern_atom(conn, "_NET_WM_STATE_DEMANDS_ATTENTION"),
+            utf8_string: intern_atom(conn, "UTF8_STRING"),
+        });
+        refresh(conn, &mut state);
+    }
+
+    fn x_event(&self, conn: &xcb::Connection, event: &xcb::GenericEvent) -> bool {
+        if event.response_type() & !0x80 != xcb::PROPERTY_NOTIFY {
+            return false;
+        }
+        let ev: &xcb::PropertyNotifyEvent = unsafe { xcb::cast_event(event) };
+
+        let mut state = self.state.lock().unwrap();
+        let relevant = match &state.atoms {
+            Some(a) if ev.window() == a.root => {
+                [a.number_of_desktops, a.current_desktop, a.desktop_names, a.client_list]
+                    .contains(&ev.atom())
+            }
+            Some(a) => {
+                state.watched.contains(&ev.window())
+                    && [a.wm_desktop, a.wm_state, xcb::ATOM_WM_HINTS].contains(&ev.atom())
+            }
+            None => false,
+        };
+        if relevant {
+            refresh(conn, &mut state);
+        }
+        relevant
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(desktop: u32, urgent: bool) -> Client {
+        Client { desktop, urgent }
+    }
+
+    #[test]
+    fn desktops() {
+        let clients = [
+            client(0, false),
+            client(1, false),
+            client(1, true),
+            client(0xffffffff, false),
+            client(3, false),
+        ];
+        let tags = tags_from_desktops(4, 3, b"web\0mail\0\0code\0", &clients);
+        let states: Vec<TagState> = tags.iter().map(|t| t.state.clone()).collect();
+        let names: Vec<&str> = tags.iter().map(|t| t.name.as_str()).collect();
+        let counts: Vec<Option<usize>> = tags.iter().map(|t| t.clients).collect();
+        assert_eq!(states, vec![
+            TagState::NonEmpty,
+            TagState::UrgentWindow,
+            TagState::Empty,
+            TagState::ThisMonitorFocused,
+        ]);
+        // an empty name falls back to the desktop number
+        assert_eq!(names, vec!["web", "mail", "3", "code"]);
+        assert_eq!(counts, vec![Some(1), Some(2), Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn desktops_without_names() {
+        let tags = tags_from_desktops(2, 0, b"", &[]);
+        let names: Vec<&str> = tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["1", "2"]);
+        assert_eq!(tags[1].state, TagState::Empty);
+    }
+
+    #[test]
+    fn desktops_invalid_utf8_name() {
+        let tags = tags_from_desktops(1, 0, b"w\xffb\0", &[]);
+        assert_eq!(tags[0].name, "w\u{fffd}b");
+    }
+}
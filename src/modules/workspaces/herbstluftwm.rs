@@ -0,0 +1,149 @@
+use crate::BarState;
+use crate::modules::herbstluftwm::hook_stream;
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::process::Command;
+use super::{Tag, TagState, WorkspaceBackend};
+
+// tags from `herbstclient tag_status`, read on every render
+pub struct HerbstluftWM {
+    // urgency doesn't depend on the monitor, the last tag_status read on
+    // any of them holds for all
+    urgent: AtomicBool,
+}
+
+impl HerbstluftWM {
+    pub fn new() -> HerbstluftWM {
+        HerbstluftWM {
+            urgent: AtomicBool::new(false),
+        }
+    }
+}
+
+fn state_from_symbol(sym: char) -> Option<TagState> {
+    match sym {
+        '.' => Some(TagState::Empty),
+        ':' => Some(TagState::NonEmpty),
+        '+' => Some(TagState::ThisMonitorUnfocused),
+        '#' => Some(TagState::ThisMonitorFocused),
+        '-' => Some(TagState::DifferentMonitorUnfocused),
+        '%' => Some(TagState::DifferentMonitorFocused),
+        '!' => Some(TagState::UrgentWindow),
+        _ => None
+    }
+}
+
+fn tag_from_str(s: &str) -> Option<Tag> {
+    let sym = s.chars().next()?;
+    let state = state_from_symbol(sym)?;
+    Some(Tag {
+        state,
+        name: String::from(&s[sym.len_utf8()..]),
+        clients: None,
+    })
+}
+
+// tags from the output of `herbstclient tag_status`
+pub fn parse_tag_status(s: &str) -> Vec<Tag> {
+    s.split('\t')
+        .filter_map(tag_from_str)
+        .collect()
+}
+
+// output of `herbstclient chain , get_attr tags.0.client_count , ...`,
+// None unless there is one count per tag
+pub fn parse_client_counts(s: &str, n_tags: usize) -> Option<Vec<usize>> {
+    let counts: Vec<usize> = s.lines()
+        .map(|l| l.trim().parse::<usize>().ok())
+        .collect::<Option<Vec<usize>>>()?;
+    if counts.len() != n_tags {
+        return None;
+    }
+    Some(counts)
+}
+
+fn read_client_counts(n_tags: usize) -> Option<Vec<usize>> {
+    let mut cmd = Command::new("herbstclient");
+    cmd.arg("chain");
+    for i in 0..n_tags {
+        cmd.arg(",").arg("get_attr").arg(format!("tags.{}.client_count", i));
+    }
+    let out = cmd.output().ok()?;
+    parse_client_counts(&String::from_utf8_lossy(&out.stdout), n_tags)
+}
+
+impl WorkspaceBackend for HerbstluftWM {
+    fn tags(&self, monitor: i32, client_counts: bool) -> Vec<Tag> {
+        let hc_output = Command::new("/usr/bin/herbstclient")
+            .arg("tag_status")
+            .arg(monitor.to_string())
+            .output()
+            .expect("failed to execute command");
+        let mut tags = parse_tag_status(&String::from_utf8_lossy(&hc_output.stdout));
+        self.urgent.store(tags.iter().any(|t| t.state == TagState::UrgentWindow),
+                          Ordering::Relaxed);
+
+        // tag_status lists tags in index order
+        if client_counts {
+            if let Some(counts) = read_client_counts(tags.len()) {
+                for (t, c) in tags.iter_mut().zip(counts) {
+                    t.clients = Some(c);
+                }
+            }
+        }
+        tags
+    }
+
+    fn urgent(&self) -> bool {
+        self.urgent.load(Ordering::Relaxed)
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        hook_stream(bar_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_status() {
+        let tags = parse_tag_status("\t#1\t:2\t.3\t-4\t%5\t!6\t+7\t\n");
+        let states: Vec<TagState> = tags.iter().map(|t| t.state.clone()).collect();
+        let names: Vec<&str> = tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(states, vec![
+            TagState::ThisMonitorFocused,
+            TagState::NonEmpty,
+            TagState::Empty,
+            TagState::DifferentMonitorUnfocused,
+            TagState::DifferentMonitorFocused,
+            TagState::UrgentWindow,
+            TagState::ThisMonitorUnfocused,
+        ]);
+        assert_eq!(names, vec!["1", "2", "3", "4", "5", "6", "7"]);
+    }
+
+    #[test]
+    fn tag_status_unicode_names() {
+        let tags = parse_tag_status("\t#λ web\t:✉\t");
+        assert_eq!(tags[0].name, "λ web");
+        assert_eq!(tags[1].name, "✉");
+        assert!(tags.iter().all(|t| t.clients.is_none()));
+    }
+
+    #[test]
+    fn tag_status_skips_unknown() {
+        let tags = parse_tag_status("\t?x\t\t:y\t");
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "y");
+        assert!(parse_tag_status("").is_empty());
+    }
+
+    #[test]
+    fn client_counts() {
+        assert_eq!(parse_client_counts("3\n0\n12\n", 3), Some(vec![3, 0, 12]));
+        assert_eq!(parse_client_counts("3\n0\n", 3), None);
+        assert_eq!(parse_client_counts("3\nfoo\n1\n", 3), None);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::BarState;
+use crate::utils::*;
+use std::sync::{Arc, Mutex, Condvar};
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use super::{Tag, TagState, WorkspaceBackend};
+
+const MAGIC: &[u8] = b"i3-ipc";
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+// set in the type of event messages
+const EVENT_BIT: u32 = 1 << 31;
+
+// workspaces from i3 IPC, refreshed on workspace and output events
+pub struct I3 {
+    workspaces: Arc<Mutex<Vec<I3Workspace>>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct I3Workspace {
+    pub name: String,
+    pub visible: bool,
+    pub focused: bool,
+    pub urgent: bool,
+    pub output: String,
+    pub x: i64,
+}
+
+impl I3 {
+    pub fn new() -> I3 {
+        I3 {
+            workspaces: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+pub fn encode_message(msg_type: u32, payload: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(MAGIC.len() + 8 + payload.len());
+    msg.extend_from_slice(MAGIC);
+    msg.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(&msg_type.to_ne_bytes());
+    msg.extend_from_slice(payload);
+    msg
+}
+
+// (type, payload) of the next message in the stream
+pub fn read_message<R: Read>(reader: &mut R) -> io::Result<(u32, Vec<u8>)> {
+    let mut header = [0u8; 14];
+    reader.read_exact(&mut header)?;
+    if &header[..6] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad i3-ipc magic"));
+    }
+    let mut len = [0u8; 4];
+    let mut msg_type = [0u8; 4];
+    len.copy_from_slice(&header[6..10]);
+    msg_type.copy_from_slice(&header[10..14]);
+    let mut payload = vec![0u8; u32::from_ne_bytes(len) as usize];
+    reader.read_exact(&mut payload)?;
+    Ok((u32::from_ne_bytes(msg_type), payload))
+}
+
+// reply to GET_WORKSPACES
+pub fn parse_workspaces(json: &[u8]) -> Option<Vec<I3Workspace>> {
+    let value: serde_json::Value = serde_json::from_slice(json).ok()?;
+    let workspaces = value.as_array()?
+        .iter()
+        .filter_map(|w| Some(I3Workspace {
+            name: String::from(w["name"].as_str()?),
+            visible: w["visible"].as_bool().unwrap_or(false),
+            focused: w["focused"].as_bool().unwrap_or(false),
+            urgent: w["urgent"].as_bool().unwrap_or(false),
+            output: String::from(w["output"].as_str().unwrap_or("")),
+            x: w["rect"]["x"].as_i64().unwrap_or(0),
+        }))
+        .collect();
+    Some(workspaces)
+}
+
+// workspaces on the monitor-th output, outputs ordered left to right
+pub fn tags_for_monitor(workspaces: &[I3Workspace], monitor: i32) -> Vec<Tag> {
+    let mut outputs: Vec<(i64, &str)> = Vec::new();
+    for w in workspaces {
+        match outputs.iter_mut().find(|(_, o)| *o == w.output) {
+            Some(o) => o.0 = o.0.min(w.x),
+            None => outputs.push((w.x, &w.output)),
+        }
+    }
+    outputs.sort();
+    let output = match outputs.get(monitor as usize) {
+        Some((_, o)) => *o,
+        None => return Vec::new(),
+    };
+
+    workspaces.iter()
+        .filter(|w| w.output == output)
+        .map(|w| {
+            let state = if w.focused {
+                TagState::ThisMonitorFocused
+            } else if w.urgent {
+                TagState::UrgentWindow
+            } else if w.visible {
+                TagState::ThisMonitorUnfocused
+            } else {
+                TagState::NonEmpty
+            };
+            Tag {
+                state,
+                name: w.name.clone(),
+                clients: None,
+            }
+        })
+        .collect()
+}
+
+// handle messages until the stream ends. `request_workspaces` is called for
+// every event, the resulting reply updates `workspaces`.
+pub fn read_messages<R: Read>(reader: &mut R,
+                              workspaces: &Mutex<Vec<I3Workspace>>,
+                              request_workspaces: &mut dyn FnMut() -> io::Result<()>,
+                              on_change: &dyn Fn()) -> io::Result<()> {
+    loop {
+        let (msg_type, payload) = read_message(reader)?;
+        if msg_type & EVENT_BIT != 0 {
+            request_workspaces()?;
+        } else if msg_type == GET_WORKSPACES {
+            if let Some(w) = parse_workspaces(&payload) {
+                *workspaces.lock().unwrap() = w;
+                on_change();
+            }
+        }
+    }
+}
+
+fn socket_path() -> Option<String> {
+    if let Ok(p) = std::env::var("I3SOCK") {
+        return Some(p);
+    }
+    let out = Command::new("i3").arg("--get-socketpath").output().ok()?;
+    let p = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    if p.is_empty() {
+        None
+    } else {
+        Some(p)
+    }
+}
+
+fn watch(workspaces: &Mutex<Vec<I3Workspace>>, on_change: &dyn Fn()) -> io::Result<()> {
+    let path = socket_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no i3 socket"))?;
+    let mut reader = UnixStream::connect(path)?;
+    let mut writer = reader.try_clone()?;
+    writer.write_all(&encode_message(SUBSCRIBE, br#"["workspace","output"]"#))?;
+    writer.write_all(&encode_message(GET_WORKSPACES, b""))?;
+    read_messages(&mut reader, workspaces, &mut || {
+        writer.write_all(&encode_message(GET_WORKSPACES, b""))
+    }, on_change)
+}
+
+impl WorkspaceBackend for I3 {
+    fn tags(&self, monitor: i32, _client_counts: bool) -> Vec<Tag> {
+        tags_for_monitor(&self.workspaces.lock().unwrap(), monitor)
+    }
+
+    fn urgent(&self) -> bool {
+        // a focused workspace is shown as focused
+        self.workspaces.lock().unwrap().iter().any(|w| w.urgent && !w.focused)
+    }
+
+    fn event_generator(&self, bar_state: Arc<(Mutex<BarState>, Condvar)>) {
+        let workspaces = self.workspaces.clone();
+        thread::spawn(move || {
+            loop {
+                // reconnect when i3 restarts
+                let _ = watch(&workspaces, &|| signal_bar_redraw(bar_state.clone()));
+                thread::sleep(Duration::from_millis(1000));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const WORKSPACES: &[u8] = br#"[
+        {"id":1,"num":1,"name":"1","visible":true,"focused":false,"urgent":false,
+         "rect":{"x":1920,"y":0,"width":1280,"height":1024},"output":"HDMI-1"},
+        {"id":2,"num":2,"name":"2:web","visible":true,"focused":true,"urgent":false,
+         "rect":{"x":0,"y":0,"width":1920,"height":1080},"output":"eDP-1"},
+        {"id":3,"num":3,"name":"3","visible":false,"focused":false,"urgent":true,
+         "rect":{"x":0,"y":0,"width":1920,"height":1080},"output":"eDP-1"},
+        {"id":4,"num":4,"name":"4","visible":false,"focused":false,"urgent":false,
+         "rect":{"x":0,"y":0,"width":1920,"height":1080},"output":"eDP-1"}
+    ]"#;
+
+    fn tag(state: TagState, name: &str) -> Tag {
+        Tag { state, name: String::from(name), clients: None }
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let mut stream = encode_message(GET_WORKSPACES, b"[]");
+        stream.extend(encode_message(EVENT_BIT, b"{\"change\":\"focus\"}"));
+        let mut reader: &[u8] = &stream;
+        assert_eq!(read_message(&mut reader).unwrap(), (GET_WORKSPACES, b"[]".to_vec()));
+        assert_eq!(read_message(&mut reader).unwrap().0, EVENT_BIT);
+        assert!(read_message(&mut reader).is_err());
+    }
+
+    #[test]
+    fn message_bad_magic() {
+        let mut stream = encode_message(GET_WORKSPACES, b"[]");
+        stream[0] = b'x';
+        assert!(read_message(&mut &stream[..]).is_err());
+    }
+
+    #[test]
+    fn message_truncated() {
+        let stream = encode_message(GET_WORKSPACES, b"[1,2,3]");
+        assert!(read_message(&mut &stream[..10]).is_err());
+        assert!(read_message(&mut &stream[..stream.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn workspaces_by_output() {
+        let ws = parse_workspaces(WORKSPACES).unwrap();
+        assert_eq!(ws.len(), 4);
+        // eDP-1 is left of HDMI-1
+        assert_eq!(tags_for_monitor(&ws, 0), vec![
+            tag(TagState::ThisMonitorFocused, "2:web"),
+            tag(TagState::UrgentWindow, "3"),
+            tag(TagState::NonEmpty, "4"),
+        ]);
+        assert_eq!(tags_for_monitor(&ws, 1), vec![tag(TagState::ThisMonitorUnfocused, "1")]);
+        assert!(tags_for_monitor(&ws, 2).is_empty());
+    }
+
+    #[test]
+    fn workspaces_invalid() {
+        assert_eq!(parse_workspaces(b"{\"success\":true}"), None);
+        assert_eq!(parse_workspaces(b"[{\"nam"), None);
+        // entries without a name are skipped
+        assert_eq!(parse_workspaces(b"[{\"num\":1}]"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn event_stream() {
+        // subscribe reply, initial workspaces, then an event
+        let mut stream = encode_message(SUBSCRIBE, b"{\"success\":true}");
+        stream.extend(encode_message(GET_WORKSPACES, b"[]"));
+        stream.extend(encode_message(EVENT_BIT, b"{\"change\":\"focus\"}"));
+        stream.extend(encode_message(EVENT_BIT | 1, b"{\"change\":\"unspecified\"}"));
+        stream.extend(encode_message(GET_WORKSPACES, WORKSPACES));
+
+        let workspaces = Mutex::new(Vec::new());
+        let requests = Cell::new(0);
+        let changes = Cell::new(0);
+        let res = read_messages(&mut &stream[..], &workspaces, &mut || {
+            requests.set(requests.get() + 1);
+            Ok(())
+        }, &|| changes.set(changes.get() + 1));
+
+        assert!(res.is_err());
+        assert_eq!(requests.get(), 2);
+        assert_eq!(changes.get(), 2);
+        assert_eq!(workspaces.lock().unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config::*;
+use std::process::Command;
+use xcb::randr;
+
+// where the bars go when running one bar per monitor, picked in config.rs
+#[allow(dead_code)]
+pub enum MonitorSource {
+    // active CRTCs, left to right
+    RandR,
+    // `herbstclient list_monitors`, in hlwm's order so the tags of each
+    // bar's monitor are the right ones
+    Herbstluftwm,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Monitor {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// "WxH+X+Y", offsets may also start with '-'
+fn parse_geometry(s: &str) -> Option<Monitor> {
+    let is_sign = |c: char| c == '+' || c == '-';
+    let (size, pos) = s.split_at(s.find(is_sign)?);
+    let (w, h) = size.split_once('x')?;
+    let (x, y) = pos.split_at(pos[1..].find(is_sign)? + 1);
+    Some(Monitor {
+        x: x.parse().ok()?,
+        y: y.parse().ok()?,
+        width: w.parse().ok()?,
+        height: h.parse().ok()?,
+    })
+}
+
+// monitors from the output of `herbstclient list_monitors`, with lines like
+// `0: 1920x1080+0+0 with tag "1" [FOCUS]`
+pub fn parse_hlwm_monitors(s: &str) -> Vec<Monitor> {
+    s.lines()
+        .filter_map(|l| l.split_whitespace().nth(1))
+        .filter_map(parse_geometry)
+        .collect()
+}
+
+fn hlwm_monitors() -> Vec<Monitor> {
+    Command::new("herbstclient")
+        .arg("list_monitors")
+        .output()
+        .map(|o| parse_hlwm_monitors(&String::from_utf8_lossy(&o.stdout)))
+        .unwrap_or_default()
+}
+
+fn randr_monitors(conn: &xcb::Connection, root: xcb::Window) -> Vec<Monitor> {
+    let resources = match randr::get_screen_resources_current(conn, root).get_reply() {
+        Ok(r) => r,
+        Err(_) => return Vec::new(),
+    };
+    let cookies: Vec<_> = resources.crtcs()
+        .iter()
+        .map(|c| randr::get_crtc_info(conn, *c, resources.config_timestamp()))
+        .collect();
+    let mut monitors: Vec<Monitor> = Vec::new();
+    for info in cookies.into_iter().filter_map(|c| c.get_reply().ok()) {
+        if info.num_outputs() == 0 || info.width() == 0 {
+            continue;
+        }
+        let m = Monitor {
+            x: info.x() as i32,
+            y: info.y() as i32,
+            width: info.width() as u32,
+            height: info.height() as u32,
+        };
+        // mirrored outputs share one bar
+        if !monitors.contains(&m) {
+            monitors.push(m);
+        }
+    }
+    monitors.sort_by_key(|m| (m.x, m.y));
+    monitors
+}
+
+// the configured monitors, falling back to RandR and then the whole screen
+pub fn detect(conn: &xcb::Connection, screen: &xcb::Screen) -> Vec<Monitor> {
+    let mut monitors = match MULTI_BAR_MONITORS {
+        MonitorSource::Herbstluftwm => hlwm_monitors(),
+        MonitorSource::RandR => Vec::new(),
+    };
+    if monitors.is_empty() {
+        monitors = randr_monitors(conn, screen.root());
+    }
+    if monitors.is_empty() {
+        monitors.push(Monitor {
+            x: 0,
+            y: 0,
+            width: screen.width_in_pixels() as u32,
+            height: screen.height_in_pixels() as u32,
+        });
+    }
+    monitors
+}
+
+// ask for RandR events on the root window, returns the first event code of
+// the extension. hlwm's monitors are also re-read on them, they usually
+// change along with the outputs.
+pub fn watch(conn: &xcb::Connection, root: xcb::Window) -> Option<u8> {
+    randr::query_version(conn, 1, 2).get_reply().ok()?;
+    let first_event = conn.get_extension_data(randr::id())?.first_event();
+    let mask = randr::NOTIFY_MASK_SCREEN_CHANGE
+        | randr::NOTIFY_MASK_CRTC_CHANGE
+        | randr::NOTIFY_MASK_OUTPUT_CHANGE;
+    randr::select_input(conn, root, mask as u16);
+    conn.flush();
+    Some(first_event)
+}
@@ -0,0 +1,341 @@
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+
+pub const NLM_F_REQUEST: u16 = 0x1;
+pub const NLM_F_MULTI: u16   = 0x2;
+pub const NLM_F_DUMP: u16    = 0x300;
+
+pub const NLMSG_ERROR: u16 = 2;
+pub const NLMSG_DONE: u16  = 3;
+
+const NLMSG_HDRLEN: usize = 16;
+const NLA_HDRLEN: usize   = 4;
+const GENL_HDRLEN: usize  = 4;
+
+// generic netlink controller, resolves family names to ids
+pub const GENL_ID_CTRL: u16        = 0x10;
+pub const CTRL_CMD_GETFAMILY: u8   = 3;
+pub const CTRL_ATTR_FAMILY_ID: u16 = 1;
+pub const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+
+// raw AF_NETLINK socket, closed on drop
+pub struct NetlinkSocket {
+    fd: RawFd,
+}
+
+impl NetlinkSocket {
+    // `groups` is the multicast group bitmask to listen to, 0 for none
+    pub fn open(protocol: i32, groups: u32) -> io::Result<NetlinkSocket> {
+        let fd = unsafe {
+            libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, protocol)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sock = NetlinkSocket { fd };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        addr.nl_groups = groups;
+        let r = unsafe {
+            libc::bind(fd,
+                       &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                       mem::size_of::<libc::sockaddr_nl>() as u32)
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(sock)
+    }
+
+    pub fn send(&self, msg: &[u8]) -> io::Result<()> {
+        let r = unsafe {
+            libc::send(self.fd, msg.as_ptr() as *const libc::c_void, msg.len(), 0)
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // send a request and collect the payloads of all replies, until
+    // NLMSG_DONE for dumps
+    pub fn request(&self, msg: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+        self.send(msg)?;
+        let seq = u32_at(msg, 8);
+        let mut replies = Vec::new();
+        let mut buf = vec![0u8; 32768];
+        loop {
+            let n = self.recv(&mut buf)?;
+            if let Some(r) = collect_replies(&buf[..n], seq, &mut replies) {
+                return r.map(|_| replies);
+            }
+        }
+    }
+
+    // blocks until a datagram arrives
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let r = unsafe {
+            libc::recv(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0)
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(r as usize)
+    }
+}
+
+impl Drop for NetlinkSocket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn u16_at(buf: &[u8], i: usize) -> u16 {
+    u16::from_ne_bytes([buf[i], buf[i + 1]])
+}
+
+fn u32_at(buf: &[u8], i: usize) -> u32 {
+    u32::from_ne_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]])
+}
+
+pub struct Message<'a> {
+    pub msg_type: u16,
+    pub flags: u16,
+    pub seq: u32,
+    pub payload: &'a [u8],
+}
+
+// netlink messages in one received datagram, stops at the first truncated one
+pub fn messages(buf: &[u8]) -> Vec<Message<'_>> {
+    let mut msgs = Vec::new();
+    let mut i = 0;
+    while i + NLMSG_HDRLEN <= buf.len() {
+        let len = u32_at(buf, i) as usize;
+        if len < NLMSG_HDRLEN || i + len > buf.len() {
+            break;
+        }
+        msgs.push(Message {
+            msg_type: u16_at(buf, i + 4),
+            flags: u16_at(buf, i + 6),
+            seq: u32_at(buf, i + 8),
+            payload: &buf[i + NLMSG_HDRLEN..i + len],
+        });
+        i += align4(len);
+    }
+    msgs
+}
+
+// adds the payloads of the replies to request `seq` in one received
+// datagram to `replies`, replies to other requests are skipped. Some once
+// the request is answered, with the error the kernel replied with.
+pub fn collect_replies(buf: &[u8], seq: u32, replies: &mut Vec<Vec<u8>>)
+                       -> Option<io::Result<()>> {
+    let msgs = messages(buf);
+    if msgs.is_empty() {
+        return Some(Ok(()));
+    }
+    for m in msgs.iter().filter(|m| m.seq == seq) {
+        match m.msg_type {
+            NLMSG_DONE => return Some(Ok(())),
+            NLMSG_ERROR => {
+                if m.payload.len() < 4 {
+                    return Some(Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                   "truncated netlink error")));
+                }
+                // errno is negated, 0 is an ack
+                let errno = u32_at(m.payload, 0) as i32;
+                if errno != 0 {
+                    return Some(Err(io::Error::from_raw_os_error(-errno)));
+                }
+                return Some(Ok(()));
+            }
+            _ => replies.push(m.payload.to_vec()),
+        }
+        if m.flags & NLM_F_MULTI == 0 {
+            return Some(Ok(()));
+        }
+    }
+    None
+}
+
+// nlmsghdr followed by the payload
+pub fn message(msg_type: u16, flags: u16, seq: u32, payload: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(NLMSG_HDRLEN + payload.len());
+    msg.extend_from_slice(&((NLMSG_HDRLEN + payload.len()) as u32).to_ne_bytes());
+    msg.extend_from_slice(&msg_type.to_ne_bytes());
+    msg.extend_from_slice(&flags.to_ne_bytes());
+    msg.extend_from_slice(&seq.to_ne_bytes());
+    msg.extend_from_slice(&0u32.to_ne_bytes());
+    msg.extend_from_slice(payload);
+    msg
+}
+
+// generic netlink message, `attrs` are already encoded attributes
+pub fn genl_message(family: u16, cmd: u8, flags: u16, seq: u32, attrs: &[u8]) -> Vec<u8> {
+    let mut payload = vec![cmd, 1, 0, 0];
+    payload.extend_from_slice(attrs);
+    message(family, flags, seq, &payload)
+}
+
+// attributes of a generic netlink payload, after the genlmsghdr
+pub fn genl_attributes(payload: &[u8]) -> Vec<(u16, &[u8])> {
+    if payload.len() < GENL_HDRLEN {
+        return Vec::new();
+    }
+    attributes(&payload[GENL_HDRLEN..])
+}
+
+// (type, data) of all attributes in buf, nested attributes can be parsed by
+// calling this again on their data
+pub fn attributes(buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut attrs = Vec::new();
+    let mut i = 0;
+    while i + NLA_HDRLEN <= buf.len() {
+        let len = u16_at(buf, i) as usize;
+        if len < NLA_HDRLEN || i + len > buf.len() {
+            break;
+        }
+        // strip NLA_F_NESTED and NLA_F_NET_BYTEORDER
+        let attr_type = u16_at(buf, i + 2) & 0x3fff;
+        attrs.push((attr_type, &buf[i + NLA_HDRLEN..i + len]));
+        i += align4(len);
+    }
+    attrs
+}
+
+pub fn attribute(attr_type: u16, data: &[u8]) -> Vec<u8> {
+    let len = NLA_HDRLEN + data.len();
+    let mut attr = Vec::with_capacity(align4(len));
+    attr.extend_from_slice(&(len as u16).to_ne_bytes());
+    attr.extend_from_slice(&attr_type.to_ne_bytes());
+    attr.extend_from_slice(data);
+    attr.resize(align4(len), 0);
+    attr
+}
+
+pub fn attr_u16(data: &[u8]) -> Option<u16> {
+    Some(u16::from_ne_bytes([*data.first()?, *data.get(1)?]))
+}
+
+pub fn attr_u32(data: &[u8]) -> Option<u32> {
+    if data.len() < 4 {
+        return None;
+    }
+    Some(u32_at(data, 0))
+}
+
+// id of a generic netlink family from the controller's reply
+pub fn parse_family_id(payload: &[u8]) -> Option<u16> {
+    genl_attributes(payload)
+        .into_iter()
+        .find(|(t, _)| *t == CTRL_ATTR_FAMILY_ID)
+        .and_then(|(_, d)| attr_u16(d))
+}
+
+pub fn resolve_family(sock: &NetlinkSocket, name: &str) -> io::Result<u16> {
+    let mut name_z = Vec::from(name.as_bytes());
+    name_z.push(0);
+    let msg = genl_message(GENL_ID_CTRL, CTRL_CMD_GETFAMILY, NLM_F_REQUEST, 1,
+                           &attribute(CTRL_ATTR_FAMILY_NAME, &name_z));
+    sock.request(&msg)?
+        .iter()
+        .find_map(|p| parse_family_id(p))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown netlink family"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_roundtrip() {
+        let mut buf = message(20, NLM_F_MULTI, 1, b"abc");
+        // padding up to the next message
+        buf.push(0);
+        buf.extend(message(NLMSG_DONE, NLM_F_MULTI, 1, &[]));
+        let msgs = messages(&buf);
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[0].msg_type, 20);
+        assert_eq!(msgs[0].flags, NLM_F_MULTI);
+        assert_eq!(msgs[0].payload, b"abc");
+        assert_eq!(msgs[1].msg_type, NLMSG_DONE);
+        assert!(msgs[1].payload.is_empty());
+    }
+
+    #[test]
+    fn messages_truncated() {
+        let mut buf = message(20, 0, 1, b"abcd");
+        buf.extend(message(21, 0, 2, b"efgh"));
+        // the second one is cut off
+        assert_eq!(messages(&buf[..buf.len() - 1]).len(), 1);
+        // header only partly there
+        assert!(messages(&buf[..NLMSG_HDRLEN - 1]).is_empty());
+        assert!(messages(&[]).is_empty());
+    }
+
+    #[test]
+    fn messages_malformed() {
+        // length shorter than the header would loop forever if accepted
+        let mut buf = message(20, 0, 1, b"abcd");
+        buf[0..4].copy_from_slice(&4u32.to_ne_bytes());
+        assert!(messages(&buf).is_empty());
+        // length past the end of the datagram
+        buf[0..4].copy_from_slice(&1000u32.to_ne_bytes());
+        assert!(messages(&buf).is_empty());
+    }
+
+    #[test]
+    fn attribute_roundtrip() {
+        let mut buf = attribute(1, &7u32.to_ne_bytes());
+        // padded to four bytes
+        buf.extend(attribute(2, b"wlan0\0"));
+        assert_eq!(buf.len(), 8 + 12);
+        let attrs = attributes(&buf);
+        assert_eq!(attrs.len(), 2);
+        assert_eq!(attrs[0].0, 1);
+        assert_eq!(attr_u32(attrs[0].1), Some(7));
+        assert_eq!(attrs[1], (2, &b"wlan0\0"[..]));
+    }
+
+    #[test]
+    fn attributes_nested_flag() {
+        let mut buf = attribute(21, &attribute(7, &[0xc4]));
+        // NLA_F_NESTED
+        buf[3] |= 0x80;
+        let attrs = attributes(&buf);
+        assert_eq!(attrs[0].0, 21);
+        assert_eq!(attributes(attrs[0].1), vec![(7, &[0xc4][..])]);
+    }
+
+    #[test]
+    fn attributes_truncated() {
+        let mut buf = attribute(1, b"abcd");
+        buf.extend(attribute(2, b"efgh"));
+        assert_eq!(attributes(&buf[..buf.len() - 2]).len(), 1);
+        assert!(attributes(&buf[..3]).is_empty());
+        // zero length
+        assert!(attributes(&[0, 0, 1, 0, 0, 0, 0, 0]).is_empty());
+        assert_eq!(attr_u32(b"abc"), None);
+        assert_eq!(attr_u16(b"a"), None);
+    }
+
+    #[test]
+    fn family_id() {
+        let mut attrs = attribute(CTRL_ATTR_FAMILY_NAME, b"nl80211\0");
+        attrs.extend(attribute(CTRL_ATTR_FAMILY_ID, &0x1cu16.to_ne_bytes()));
+        let msg = genl_message(GENL_ID_CTRL, 1, 0, 1, &attrs);
+        let msgs = messages(&msg);
+        assert_eq!(parse_family_id(msgs[0].payload), Some(0x1c));
+        // the genl header alone
+        assert_eq!(parse_family_id(&[1, 1, 0]), None);
+        assert_eq!(parse_family_id(&[1, 1, 0, 0]), None);
+    }
+}
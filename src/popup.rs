@@ -0,0 +1,199 @@
+use crate::DynamicConfig;
+use crate::utils::*;
+
+const KEYSYM_ESCAPE: xcb::Keysym = 0xff1b;
+
+// what an X event means for a popup
+pub enum PopupEvent {
+    // not meant for the popup
+    None,
+    // (part of) the window needs to be redrawn
+    Expose,
+    // button press inside the popup
+    Button { button: u8 },
+    // click outside or Escape, the popup should be closed
+    Dismiss,
+}
+
+// an override-redirect window next to a bar module, e.g. the clock's
+// calendar. It grabs pointer and keyboard while open, so clicks anywhere
+// else (the bar included) and Escape can close it. Its events arrive
+// through the module's x_event.
+pub struct Popup {
+    window: xcb::Window,
+    pixmap: xcb::Pixmap,
+    gcontext: xcb::Gcontext,
+    cairo: cairo::Context,
+    // in device pixels
+    width: f64,
+    height: f64,
+    escape: Option<xcb::Keycode>,
+}
+
+// keycode that produces keysym in the core keyboard mapping
+fn keycode_for(conn: &xcb::Connection, keysym: xcb::Keysym) -> Option<xcb::Keycode> {
+    let setup = conn.get_setup();
+    let (min, max) = (setup.min_keycode(), setup.max_keycode());
+    let reply = xcb::get_keyboard_mapping(conn, min, max - min + 1).get_reply().ok()?;
+    let per = reply.keysyms_per_keycode().max(1) as usize;
+    reply.keysyms()
+        .iter()
+        .position(|k| *k == keysym)
+        .map(|i| min + (i / per) as u8)
+}
+
+impl Popup {
+    // opens a width x height popup below the block x0..x1 of the bar, or
+    // above it if the bar is in the lower half of the screen. Sizes are
+    // unscaled like everything modules draw, dyn_config is the one they got.
+    // None if another client holds a grab, the popup couldn't be closed then.
+    pub fn open(conn: &xcb::Connection, screen_num: i32, dyn_config: &DynamicConfig,
+                x0: f64, x1: f64, width: f64, height: f64) -> Option<Popup> {
+        let setup = conn.get_setup();
+        let screen = setup.roots().nth(screen_num as usize).unwrap();
+        let screen_w = screen.width_in_pixels() as f64;
+        let screen_h = screen.height_in_pixels() as f64;
+        let scale = dyn_config.scale;
+        let (width, height) = ((width * scale).ceil(), (height * scale).ceil());
+
+        // centered on the block, but kept on the screen
+        let x = (dyn_config.x_offset + 0.5 * scale * (x0 + x1) - 0.5 * width)
+            .min(screen_w - width)
+            .max(0.0);
+        let y = if dyn_config.y_offset > 0.5 * screen_h {
+            dyn_config.y_offset - height
+        } else {
+            dyn_config.y_offset + scale * dyn_config.height
+        };
+
+        let window = conn.generate_id();
+        let ev_mask = xcb::EVENT_MASK_EXPOSURE
+            | xcb::EVENT_MASK_KEY_PRESS
+            | xcb::EVENT_MASK_BUTTON_PRESS;
+        xcb::create_window(conn,
+                           xcb::COPY_FROM_PARENT as u8,
+                           window,
+                           screen.root(),
+                           x as i16,
+                           y as i16,
+                           width as u16,
+                           height as u16,
+                           0,
+                           xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
+                           screen.root_visual(),
+                           &[
+                               (xcb::CW_EVENT_MASK, ev_mask),
+                               (xcb::CW_OVERRIDE_REDIRECT, 1)
+                           ]);
+
+        // double buffered like the bar
+        let pixmap = conn.generate_id();
+        xcb::create_pixmap(conn, screen.root_depth(), pixmap, screen.root(),
+                           width as u16, height as u16);
+        let gcontext = conn.generate_id();
+        xcb::create_gc(conn, gcontext, window, &[(xcb::GC_GRAPHICS_EXPOSURES, 0)]);
+
+        let root_visual_type = &mut get_root_visual_type(&screen).base
+            as *mut _ as *mut cairo_sys::xcb_visualtype_t;
+        let raw_cairo_conn = conn.get_raw_conn() as *mut cairo_sys::xcb_connection_t;
+        let (cairo_conn, visual_type) = unsafe {
+            (cairo::XCBConnection::from_raw_none(raw_cairo_conn),
+             cairo::XCBVisualType::from_raw_none(root_visual_type))
+        };
+        let surface = cairo::XCBSurface::create(&cairo_conn,
+                                                &cairo::XCBDrawable(pixmap),
+                                                &visual_type,
+                                                width as i32,
+                                                height as i32)
+            .expect("failed to create XCBSurface");
+
+        // the window has to be viewable before it can be grabbed on
+        xcb::map_window(conn, window);
+        let pointer = xcb::grab_pointer(conn, false, window,
+                                        xcb::EVENT_MASK_BUTTON_PRESS as u16,
+                                        xcb::GRAB_MODE_ASYNC as u8,
+                                        xcb::GRAB_MODE_ASYNC as u8,
+                                        xcb::NONE, xcb::NONE, xcb::CURRENT_TIME);
+        let keyboard = xcb::grab_keyboard(conn, false, window, xcb::CURRENT_TIME,
+                                          xcb::GRAB_MODE_ASYNC as u8,
+                                          xcb::GRAB_MODE_ASYNC as u8);
+        let grabbed = |status: Result<u8, xcb::GenericError>| {
+            status.ok() == Some(xcb::GRAB_STATUS_SUCCESS as u8)
+        };
+        let pointer = grabbed(pointer.get_reply().map(|r| r.status()));
+        let keyboard = grabbed(keyboard.get_reply().map(|r| r.status()));
+
+        let cairo = cairo::Context::new(&surface);
+        cairo.scale(scale, scale);
+
+        let popup = Popup {
+            window,
+            pixmap,
+            gcontext,
+            cairo,
+            width,
+            height,
+            escape: keycode_for(conn, KEYSYM_ESCAPE),
+        };
+        if !(pointer && keyboard) {
+            // close also releases the grab that did succeed
+            popup.close(conn);
+            return None;
+        }
+        conn.flush();
+        Some(popup)
+    }
+
+    // draw here, then call show
+    pub fn cairo(&self) -> &cairo::Context {
+        &self.cairo
+    }
+
+    pub fn show(&self, conn: &xcb::Connection) {
+        self.cairo.get_target().flush();
+        xcb::copy_area(conn, self.pixmap, self.window, self.gcontext, 0, 0, 0, 0,
+                       self.width as u16, self.height as u16);
+        conn.flush();
+    }
+
+    pub fn close(self, conn: &xcb::Connection) {
+        xcb::ungrab_pointer(conn, xcb::CURRENT_TIME);
+        xcb::ungrab_keyboard(conn, xcb::CURRENT_TIME);
+        xcb::destroy_window(conn, self.window);
+        xcb::free_pixmap(conn, self.pixmap);
+        xcb::free_gc(conn, self.gcontext);
+        conn.flush();
+    }
+
+    pub fn event(&self, event: &xcb::GenericEvent) -> PopupEvent {
+        match event.response_type() & !0x80 {
+            xcb::EXPOSE => {
+                let ev: &xcb::ExposeEvent = unsafe { xcb::cast_event(event) };
+                if ev.window() == self.window {
+                    return PopupEvent::Expose;
+                }
+            }
+            xcb::BUTTON_PRESS => {
+                // with the grab, presses anywhere are reported relative to
+                // the popup
+                let ev: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(event) };
+                if ev.event() != self.window {
+                    return PopupEvent::None;
+                }
+                let (x, y) = (ev.event_x() as f64, ev.event_y() as f64);
+                if x < 0.0 || y < 0.0 || x >= self.width || y >= self.height {
+                    return PopupEvent::Dismiss;
+                }
+                return PopupEvent::Button { button: ev.detail() };
+            }
+            xcb::KEY_PRESS => {
+                let ev: &xcb::KeyPressEvent = unsafe { xcb::cast_event(event) };
+                if ev.event() == self.window && Some(ev.detail()) == self.escape {
+                    return PopupEvent::Dismiss;
+                }
+            }
+            _ => {}
+        }
+        PopupEvent::None
+    }
+}
@@ -0,0 +1,76 @@
+use crate::config::*;
+use crate::utils::*;
+use xcb::randr;
+
+// pixel sizes in config.rs and font sizes are meant for this DPI
+const BASE_DPI: f64 = 96.0;
+
+// how the scale factor of a bar is picked, it applies to everything drawn
+// on the bar, text included. Picked in config.rs.
+#[allow(dead_code)]
+pub enum Scale {
+    Fixed(f64),
+    // Xft.dpi from the X resources, the same on every monitor
+    Xft,
+    // the DPI of the monitor the bar is on, from the physical size RandR
+    // reports for it. Rounded to quarter steps, monitors are rarely exact.
+    Physical,
+}
+
+// the Xft.dpi value from the contents of the RESOURCE_MANAGER property,
+// which holds lines like "Xft.dpi:\t192"
+pub fn parse_xft_dpi(resources: &str) -> Option<f64> {
+    resources.lines()
+        .filter_map(|l| l.split_once(':'))
+        .find(|(k, _)| k.trim() == "Xft.dpi")
+        .and_then(|(_, v)| v.trim().parse::<f64>().ok())
+        .filter(|dpi| *dpi > 0.0)
+}
+
+fn xft_dpi(conn: &xcb::Connection, root: xcb::Window) -> Option<f64> {
+    let resource_manager = intern_atom(conn, "RESOURCE_MANAGER");
+    let reply = xcb::get_property(conn, false, root, resource_manager, xcb::ATOM_STRING,
+                                  0, 1 << 16)
+        .get_reply()
+        .ok()?;
+    parse_xft_dpi(&String::from_utf8_lossy(reply.value::<u8>()))
+}
+
+// horizontal DPI of the output whose CRTC contains (x, y)
+fn physical_dpi(conn: &xcb::Connection, root: xcb::Window, x: i32, y: i32) -> Option<f64> {
+    let resources = randr::get_screen_resources_current(conn, root).get_reply().ok()?;
+    let timestamp = resources.config_timestamp();
+    for crtc in resources.crtcs() {
+        let info = match randr::get_crtc_info(conn, *crtc, timestamp).get_reply() {
+            Ok(i) => i,
+            Err(_) => continue,
+        };
+        let (cx, cy) = (info.x() as i32, info.y() as i32);
+        let inside = x >= cx && x < cx + info.width() as i32
+            && y >= cy && y < cy + info.height() as i32;
+        if !inside || info.num_outputs() == 0 {
+            continue;
+        }
+        let output = randr::get_output_info(conn, info.outputs()[0], timestamp)
+            .get_reply()
+            .ok()?;
+        // projectors and some VMs report 0mm
+        if output.mm_width() == 0 {
+            return None;
+        }
+        return Some(info.width() as f64 * 25.4 / output.mm_width() as f64);
+    }
+    None
+}
+
+// scale factor for a bar with its top left corner at (x, y), 1.0 if the
+// configured source doesn't know
+pub fn detect(conn: &xcb::Connection, screen: &xcb::Screen, x: i32, y: i32) -> f64 {
+    let scale = match SCALE {
+        Scale::Fixed(s) => Some(s),
+        Scale::Xft => xft_dpi(conn, screen.root()).map(|dpi| dpi / BASE_DPI),
+        Scale::Physical => physical_dpi(conn, screen.root(), x, y)
+            .map(|dpi| (dpi / BASE_DPI * 4.0).round() / 4.0),
+    };
+    scale.filter(|s| *s > 0.0).unwrap_or(1.0)
+}
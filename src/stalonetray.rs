@@ -1,27 +1,32 @@
 use std::sync::{Arc, Mutex, Condvar};
 use std::thread;
 use regex::Regex;
-use std::io::{BufRead, BufReader};
+use std::io::BufReader;
 use std::process::{Command, Stdio};
 
 use crate::BarState;
 use crate::config::*;
+use crate::utils::*;
 
 pub fn run(bar_state: Arc<(Mutex<BarState>,Condvar)>) {
     let cmd;
     {
         let b = bar_state.0.lock().unwrap();
+        let dyn_config = &b.bars[0].dyn_config;
+        // the bar is scaled already, icons are as high as it in device
+        // pixels
         // TODO: how to calculate alignment position? (-1920)
         cmd = format!("stalonetray \
                       --icon-size {} \
-                      --background \"#{:x}\" \
+                      --background \"#{:06x}\" \
                       --grow-gravity E \
-                      --geometry 1x1-{}+0 \
+                      --geometry 1x1-{}+{} \
                       --kludges force_icons_size \
                       --log-level info 2>&1",
-                      b.dyn_config.height,
-                      COLOR_BG_STALONETRAY,
-                      b.dyn_config.stalone_offset);
+                      dyn_config.height,
+                      COLOR_BG_STALONETRAY & 0xffffff,
+                      dyn_config.stalone_offset,
+                      dyn_config.y_offset);
     }
     let re = Regex::new(r"geometry: \d+x\d+\+(\d+)*").unwrap();
     thread::spawn(move || {
@@ -34,14 +39,16 @@ pub fn run(bar_state: Arc<(Mutex<BarState>,Condvar)>) {
             .stdout
             .expect("failed to execute command");
         let reader = BufReader::new(out);
-        reader.lines()
-            .filter_map(|line| line.ok())
+        lossy_lines(reader)
             .for_each(|line| {
                 for c in re.captures_iter(&line.to_string()) {
-                    // resize the bar to the new stalonetray width
-                    let w = &c[1].parse::<f64>().ok().unwrap();
+                    // resize the bar to the new stalonetray width, the
+                    // tray's x is relative to the screen
+                    let x = &c[1].parse::<f64>().ok().unwrap();
                     let mut b = bar_state.0.lock().unwrap();
-                    b.dyn_config.width = *w;
+                    if let Some(bar) = b.bars.first_mut() {
+                        bar.dyn_config.width = *x - bar.dyn_config.x_offset;
+                    }
                 }
             });
     });
@@ -1,16 +1,45 @@
 use crate::BarState;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
 
+// colors are 0xAARRGGBB. An alpha of 0 is opaque, colors from before
+// there was an alpha channel are 0xRRGGBB.
 pub fn cairo_source_rgb_hex(cairo: &cairo::Context, color: u32) {
-    cairo.set_source_rgb(
+    let alpha = match (color >> 24) & 0xff {
+        0 => 0xff,
+        a => a,
+    };
+    cairo.set_source_rgba(
         ((color >> 16) & 0xff) as f64 / 255.0,
         ((color >> 8) & 0xff) as f64 / 255.0,
-        ((color >> 0) & 0xff) as f64 / 255.0);
+        (color & 0xff) as f64 / 255.0,
+        alpha as f64 / 255.0);
+}
+
+// rectangle path with corners of radius r, as far as the size allows
+pub fn cairo_rounded_rectangle(cairo: &cairo::Context, x: f64, y: f64, w: f64, h: f64,
+                               r: f64) {
+    let r = r.min(0.5 * w).min(0.5 * h).max(0.0);
+    if r == 0.0 {
+        cairo.rectangle(x, y, w, h);
+        return;
+    }
+    let pi = std::f64::consts::PI;
+    cairo.new_sub_path();
+    cairo.arc(x + w - r, y + r, r, -0.5 * pi, 0.0);
+    cairo.arc(x + w - r, y + h - r, r, 0.0, 0.5 * pi);
+    cairo.arc(x + r, y + h - r, r, 0.5 * pi, pi);
+    cairo.arc(x + r, y + r, r, pi, 1.5 * pi);
+    cairo.close_path();
 }
 
 // set the color to a fade from red to green for p in [0, 1]
 pub fn cairo_source_rgb_rgfade(cairo: &cairo::Context, p: f64) {
-    assert!(p >= 0.0 && p <= 1.0);
+    assert!((0.0..=1.0).contains(&p));
     if p <= 0.5 { // red to yellow
         cairo.set_source_rgb(1.0, 2.0*p, 0.0);
     } else { // yellow to green
@@ -19,11 +48,75 @@ pub fn cairo_source_rgb_rgfade(cairo: &cairo::Context, p: f64) {
 }
 
 pub fn setup_pango_layout(cairo: &cairo::Context, font: String) -> pango::Layout {
-    let pango_layout = pangocairo::create_layout(&cairo)
+    let pango_layout = pangocairo::create_layout(cairo)
         .expect("failed create pango layout");
     let font_description = pango::FontDescription::from_string(&font[..]);
     pango_layout.set_font_description(Some(&font_description));
-    return pango_layout
+    pango_layout
+}
+
+// lines of a stream, invalid UTF-8 is replaced instead of ending it. Only
+// a read error ends the stream.
+pub fn lossy_lines<R: BufRead>(reader: R) -> impl Iterator<Item = String> {
+    reader.split(b'\n')
+        .map_while(Result::ok)
+        .map(|l| String::from_utf8_lossy(&l).into_owned())
+}
+
+pub fn superscript(n: usize) -> String {
+    const DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+    n.to_string()
+        .chars()
+        .map(|c| DIGITS[c.to_digit(10).unwrap() as usize])
+        .collect()
+}
+
+// replace every `{name}` in fmt by its value
+pub fn format_placeholders(fmt: &str, values: &[(&str, String)]) -> String {
+    let mut s = String::from(fmt);
+    for (name, value) in values {
+        s = s.replace(&format!("{{{}}}", name), value);
+    }
+    s
+}
+
+// e.g. 1536 -> "1.5K", binary prefixes
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "K", "M", "G", "T", "P"];
+    let mut v = bytes as f64;
+    let mut unit = 0;
+    while v >= 1024.0 && unit < UNITS.len() - 1 {
+        v /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 || v >= 10.0 {
+        format!("{:.0}{}", v, UNITS[unit])
+    } else {
+        format!("{:.1}{}", v, UNITS[unit])
+    }
+}
+
+// the two largest units, e.g. "3d 4h", "4h 12m" or "12m"
+pub fn compact_duration(secs: u64) -> String {
+    let units = [(secs / 86400, "d"), (secs / 3600 % 24, "h"), (secs / 60 % 60, "m")];
+    let parts: Vec<String> = units.iter()
+        .skip_while(|(v, _)| *v == 0)
+        .take(2)
+        .map(|(v, u)| format!("{}{}", v, u))
+        .collect();
+    if parts.is_empty() {
+        String::from("0m")
+    } else {
+        parts.join(" ")
+    }
+}
+
+// "~/" at the start of path is relative to $HOME
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
 }
 
 pub fn get_root_visual_type(screen: &xcb::Screen) -> xcb::Visualtype {
@@ -37,9 +130,71 @@ pub fn get_root_visual_type(screen: &xcb::Screen) -> xcb::Visualtype {
     panic!("no visual type found");
 }
 
+// a 32 bit TrueColor visual, for windows with an alpha channel
+pub fn get_argb_visual_type(screen: &xcb::Screen) -> Option<xcb::Visualtype> {
+    screen.allowed_depths()
+        .filter(|d| d.depth() == 32)
+        .flat_map(|d| d.visuals())
+        .find(|v| v.class() == xcb::VISUAL_CLASS_TRUE_COLOR as u8)
+}
+
+// whether a compositing manager runs on the screen, without one the alpha
+// channel of windows is ignored
+pub fn compositor_running(conn: &xcb::Connection, screen_num: i32) -> bool {
+    let atom = intern_atom(conn, &format!("_NET_WM_CM_S{}", screen_num));
+    xcb::get_selection_owner(conn, atom)
+        .get_reply()
+        .is_ok_and(|r| r.owner() != xcb::NONE)
+}
+
 pub fn signal_bar_redraw(bar_state: Arc<(Mutex<BarState>, Condvar)>) {
     let mut b = bar_state.0.lock().unwrap();
     let c = &bar_state.1;
     b.redraw_signaled = true;
     c.notify_one();
 }
+
+pub fn intern_atom(conn: &xcb::Connection, name: &str) -> xcb::Atom {
+    xcb::intern_atom(conn, false, name)
+        .get_reply()
+        .expect("failed to intern atom")
+        .atom()
+}
+
+// client windows modules get PropertyNotify events for on the bar's
+// connection, with the number of modules watching each. The event mask is
+// per connection, so it's only cleared once no module watches the window.
+static WATCHED_WINDOWS: Mutex<BTreeMap<xcb::Window, usize>> = Mutex::new(BTreeMap::new());
+
+pub fn watch_window(conn: &xcb::Connection, win: xcb::Window) {
+    let mut watched = WATCHED_WINDOWS.lock().unwrap();
+    let n = watched.entry(win).or_insert(0);
+    if *n == 0 {
+        xcb::change_window_attributes(conn, win, &[
+            (xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)
+        ]);
+    }
+    *n += 1;
+}
+
+pub fn unwatch_window(conn: &xcb::Connection, win: xcb::Window) {
+    let mut watched = WATCHED_WINDOWS.lock().unwrap();
+    let n = match watched.get_mut(&win) {
+        Some(n) => n,
+        None => return,
+    };
+    *n -= 1;
+    if *n == 0 {
+        watched.remove(&win);
+        xcb::change_window_attributes(conn, win, &[
+            (xcb::CW_EVENT_MASK, xcb::EVENT_MASK_NO_EVENT)
+        ]);
+    }
+}
+
+// runs a shell command without waiting for it, it's reaped in the background
+pub fn spawn_command(cmd: &str) {
+    if let Ok(mut child) = Command::new("bash").arg("-c").arg(cmd).spawn() {
+        thread::spawn(move || child.wait());
+    }
+}